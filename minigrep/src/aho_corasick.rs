@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// Finds any of a set of literal strings in one pass over the text, however
+/// many strings there are.
+///
+/// The strings are stored in a trie whose nodes also link to the node for
+/// their longest proper suffix that is in the trie, so when the next byte
+/// has nowhere to go the search falls back along those links instead of
+/// starting over.
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    states: Vec<State>,
+    lens: Vec<usize>,
+    longest: usize,
+    has_empty: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    /// Transitions sorted by byte, so lookups can binary search.
+    next: Vec<(u8, usize)>,
+    fail: usize,
+    /// Every pattern that ends at this state, including through suffixes.
+    outputs: Vec<usize>,
+}
+
+impl State {
+    fn go(&self, byte: u8) -> Option<usize> {
+        self.next
+            .binary_search_by_key(&byte, |&(b, _)| b)
+            .ok()
+            .map(|i| self.next[i].1)
+    }
+}
+
+impl AhoCorasick {
+    pub fn new<P: AsRef<[u8]>>(patterns: &[P]) -> AhoCorasick {
+        let mut states = vec![State::default()];
+        let mut lens = Vec::with_capacity(patterns.len());
+
+        for (id, pattern) in patterns.iter().enumerate() {
+            let pattern = pattern.as_ref();
+            let mut current = 0;
+            for &byte in pattern {
+                current = match states[current].go(byte) {
+                    Some(next) => next,
+                    None => {
+                        states.push(State::default());
+                        let next = states.len() - 1;
+                        let transitions = &mut states[current].next;
+                        let at = transitions.partition_point(|&(b, _)| b < byte);
+                        transitions.insert(at, (byte, next));
+                        next
+                    }
+                };
+            }
+            states[current].outputs.push(id);
+            lens.push(pattern.len());
+        }
+
+        // Breadth first, so a state's suffix link is always finished before
+        // the states below it need it.
+        let mut queue: VecDeque<usize> = states[0].next.iter().map(|&(_, s)| s).collect();
+        while let Some(state) = queue.pop_front() {
+            for (byte, child) in states[state].next.clone() {
+                let mut fallback = states[state].fail;
+                let fail = loop {
+                    if let Some(next) = states[fallback].go(byte) {
+                        break next;
+                    }
+                    if fallback == 0 {
+                        break 0;
+                    }
+                    fallback = states[fallback].fail;
+                };
+                states[child].fail = fail;
+                let inherited = states[fail].outputs.clone();
+                states[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+
+        AhoCorasick {
+            states,
+            longest: lens.iter().copied().max().unwrap_or(0),
+            has_empty: lens.contains(&0),
+            lens,
+        }
+    }
+
+    /// The lengths of the patterns that occur in `haystack` starting
+    /// exactly at byte `start`, longest first.
+    pub fn prefix_lens(&self, haystack: &[u8], start: usize) -> Vec<usize> {
+        let mut lens = Vec::new();
+        let mut state = 0;
+        for (depth, &byte) in haystack[start..].iter().enumerate() {
+            let Some(next) = self.states[state].go(byte) else {
+                break;
+            };
+            state = next;
+            // Outputs inherited through suffix links started later.
+            if self.states[state]
+                .outputs
+                .iter()
+                .any(|&id| self.lens[id] == depth + 1)
+            {
+                lens.push(depth + 1);
+            }
+        }
+        if self.has_empty {
+            lens.push(0);
+        }
+        lens.reverse();
+        lens
+    }
+
+    pub fn pattern_count(&self) -> usize {
+        self.lens.len()
+    }
+
+    /// Finds the leftmost match starting at or after byte `start`,
+    /// preferring the longest pattern when several start at the same place.
+    /// Returns the pattern's index along with where it matched.
+    pub fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, Range<usize>)> {
+        let mut best: Option<(usize, Range<usize>)> = None;
+        let mut state = 0;
+
+        for (at, &byte) in haystack.iter().enumerate().skip(start) {
+            // Nothing that ends from here on can start before the best match.
+            if let Some((_, found)) = &best
+                && at >= found.start + self.longest
+            {
+                break;
+            }
+
+            state = loop {
+                if let Some(next) = self.states[state].go(byte) {
+                    break next;
+                }
+                if state == 0 {
+                    break 0;
+                }
+                state = self.states[state].fail;
+            };
+
+            for &id in &self.states[state].outputs {
+                let found = at + 1 - self.lens[id]..at + 1;
+                let better = best.as_ref().is_none_or(|(_, current)| {
+                    found.start < current.start
+                        || (found.start == current.start && found.end > current.end)
+                });
+                if better {
+                    best = Some((id, found));
+                }
+            }
+        }
+
+        if self.has_empty && best.as_ref().is_none_or(|(_, found)| found.start > start) {
+            let id = self.lens.iter().position(|&len| len == 0)?;
+            return Some((id, start..start));
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leftmost_longest() {
+        let automaton = AhoCorasick::new(&["he", "she", "hers", "his"]);
+        assert_eq!(Some((1, 1..4)), automaton.find_at(b"ushers", 0));
+        assert_eq!(Some((2, 2..6)), automaton.find_at(b"ushers", 2));
+        assert_eq!(None, automaton.find_at(b"ushers", 3));
+    }
+
+    #[test]
+    fn patterns_starting_at_one_place() {
+        let automaton = AhoCorasick::new(&["he", "she", "hers", "h"]);
+        assert_eq!(vec![4, 2, 1], automaton.prefix_lens(b"ushers", 2));
+        assert!(automaton.prefix_lens(b"ushers", 3).is_empty());
+    }
+
+    #[test]
+    fn thousands_of_patterns() {
+        let patterns: Vec<String> = (0..5000).map(|n| format!("token{n:04}")).collect();
+        let automaton = AhoCorasick::new(&patterns);
+        let line = b"nothing here, but then token4242 and token0007";
+        assert_eq!(Some((4242, 23..32)), automaton.find_at(line, 0));
+        assert_eq!(Some((7, 37..46)), automaton.find_at(line, 32));
+    }
+}
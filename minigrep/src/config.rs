@@ -0,0 +1,686 @@
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use crate::SearchOptions;
+use crate::encoding::Encoding;
+use crate::error::MinigrepError;
+use crate::fold::Locale;
+use crate::matcher::Matcher;
+use crate::pattern::{Pattern, PatternError};
+use crate::records::{Format, JsonPath, Selector};
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY [PATH]...
+       minigrep [OPTIONS] (-e QUERY | -f FILE)... [PATH]...
+       minigrep index DIR
+
+Searches each PATH for lines containing QUERY. Directories are searched
+recursively. With no PATH, or when PATH is -, standard input is read.
+
+'minigrep index DIR' records which files under DIR contain which three-letter
+sequences, so that searches with --index can skip files that can't match.
+Running it again only rereads files that changed. To search for the word
+\"index\", write 'minigrep -- index'.
+
+Options:
+  -i, --ignore-case         Match regardless of case
+  -s, --case-sensitive      Match case exactly, even if IGNORE_CASE is set
+  -S, --smart-case          Ignore case only if QUERY has no uppercase letters
+      --locale LANG         Fold case using LANG's rules (e.g. tr for Turkish)
+  -n, --line-number         Prefix each line with its line number
+  -c, --count               Print only the number of matching lines
+  -v, --invert-match        Select lines that do not match
+  -w, --word                Only match whole words
+  -A, --after-context NUM   Print NUM lines of context after each match
+  -B, --before-context NUM  Print NUM lines of context before each match
+  -C, --context NUM         Print NUM lines of context around each match
+  -j, --threads NUM         Search NUM files at once (default: one per CPU)
+      --index               Use the index built by 'minigrep index' to skip
+                            files in directories that can't match
+      --format FORMAT       Read input as csv, tsv or jsonl records instead of
+                            lines; guessed from the file extension if needed
+      --field NAME          Only search the CSV or TSV column NAME (or number)
+      --json-path PATH      Only search the JSON Lines value at PATH, such as
+                            .user.id or .items[0]
+  -F, --follow              Keep reading PATH as it grows, like 'tail -f',
+                            following it through truncation and rotation
+      --encoding ENC        Read input as utf8, latin1, utf16le or utf16be
+                            rather than going by its byte order mark; invalid
+                            UTF-8 is searched with the bad bytes replaced
+  -z, --search-zip          Search the contents of gzip-compressed files
+  -e, --pattern QUERY       Search for QUERY; repeat to search for any of several
+  -f, --pattern-file FILE   Search for each line of FILE as a QUERY
+      --all-match           Select lines that match every QUERY, not just one
+      --regex               Treat QUERY as a regular expression
+      --fuzzy NUM           Also match text up to NUM typos away from QUERY,
+                            printing how many edits each line needed
+      --color WHEN          Highlight matches: auto (the default), always or
+                            never; auto respects NO_COLOR
+  -r, --replace TEXT        Print lines with each match replaced by TEXT, where
+                            $0 is the match and $1, ${2}, ... regex groups
+      --in-place            Write the replacements back to the files
+      --dry-run             Show the --in-place changes as a diff instead
+      --json                Print results as JSON Lines: begin, match, context
+                            and end records per file, then a summary
+      --help                Print this help and exit
+      --version             Print the version and exit
+      --                    Treat every following argument as QUERY or PATH
+
+The IGNORE_CASE environment variable turns on --ignore-case when set to
+\"true\" or \"1\"; the flags above take precedence over it.
+
+The exit status is 0 if any line was selected, 1 if none was and 2 if an
+error occurred.";
+
+pub const VERSION: &str = concat!("minigrep ", env!("CARGO_PKG_VERSION"));
+
+/// What the command line asked minigrep to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Search(Config),
+    /// Build or update the index of a directory.
+    Index(String),
+    Help,
+    Version,
+}
+
+/// When to highlight output with ANSI colors.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// Only when stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn parse(value: &str) -> Result<ColorChoice, String> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "invalid value '{value}' for '--color' (expected auto, always or never)"
+            )),
+        }
+    }
+
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    /// The queries to look for; lines matching any of them are selected.
+    pub patterns: Vec<String>,
+    /// Select lines matching every one of `patterns` instead.
+    pub all_match: bool,
+    pub paths: Vec<String>,
+    pub ignore_case: bool,
+    pub locale: Locale,
+    pub regex: bool,
+    /// Match approximately, allowing up to this many edits.
+    pub fuzzy: Option<usize>,
+    pub line_number: bool,
+    pub count: bool,
+    pub invert_match: bool,
+    pub word: bool,
+    pub json: bool,
+    pub color: ColorChoice,
+    /// Replace each match with this template when printing or editing.
+    pub replace: Option<String>,
+    pub in_place: bool,
+    pub dry_run: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    /// How many files to search at once; 0 picks one per CPU.
+    pub threads: usize,
+    /// Decompress compressed inputs before searching them.
+    pub search_zip: bool,
+    /// Only search the files in each directory that its index allows.
+    pub index: bool,
+    /// Keep searching lines appended to the file.
+    pub follow: bool,
+    /// Decode input from this encoding instead of detecting it.
+    pub encoding: Option<Encoding>,
+    /// Search records of this format rather than lines.
+    pub format: Option<Format>,
+    /// Restrict the query to one field of each record.
+    pub selector: Option<Selector>,
+}
+
+/// Options that are switched on by their presence alone.
+const FLAGS: [&str; 17] = [
+    "ignore-case",
+    "case-sensitive",
+    "smart-case",
+    "line-number",
+    "count",
+    "invert-match",
+    "word",
+    "regex",
+    "all-match",
+    "json",
+    "in-place",
+    "dry-run",
+    "search-zip",
+    "index",
+    "follow",
+    "help",
+    "version",
+];
+
+/// Options that consume a value, either inline (`-A3`, `--context=3`) or
+/// from the next argument.
+const TAKES_VALUE: [&str; 14] = [
+    "after-context",
+    "before-context",
+    "context",
+    "locale",
+    "threads",
+    "color",
+    "replace",
+    "pattern",
+    "pattern-file",
+    "fuzzy",
+    "format",
+    "field",
+    "json-path",
+    "encoding",
+];
+
+fn next_value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("option '{option}' requires a value"))
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number '{value}' for '--{name}'"))
+}
+
+fn long_name(short: char) -> Option<&'static str> {
+    let name = match short {
+        'i' => "ignore-case",
+        's' => "case-sensitive",
+        'S' => "smart-case",
+        'n' => "line-number",
+        'c' => "count",
+        'v' => "invert-match",
+        'w' => "word",
+        'A' => "after-context",
+        'B' => "before-context",
+        'C' => "context",
+        'j' => "threads",
+        'z' => "search-zip",
+        'F' => "follow",
+        'r' => "replace",
+        'e' => "pattern",
+        'f' => "pattern-file",
+        _ => return None,
+    };
+    Some(name)
+}
+
+impl Config {
+    /// Parses the program arguments, including the program name.
+    ///
+    /// Short flags can be combined (`-in`), and everything after `--` is
+    /// taken as the query or a path even if it starts with `-`.
+    ///
+    /// A pattern file that can't be read is an I/O error naming it; anything
+    /// else wrong is `BadArgs`.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Command, MinigrepError> {
+        let mut args = args.skip(1).peekable();
+        if args.next_if(|arg| arg == "index").is_some() {
+            return match (args.next(), args.next()) {
+                (Some(arg), None) if arg == "--help" => Ok(Command::Help),
+                (Some(dir), None) => Ok(Command::Index(dir)),
+                _ => Err(String::from("'index' takes exactly one directory").into()),
+            };
+        }
+
+        let mut config = Config::default();
+        let mut ignore_case = None;
+        let mut smart_case = false;
+        let mut patterns = Vec::new();
+        let mut pattern_file = false;
+        let mut positional = Vec::new();
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                positional.extend(args.by_ref());
+                break;
+            }
+
+            let mut options = Vec::new();
+            if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let value = match value {
+                    None if TAKES_VALUE.contains(&name) => Some(next_value(&mut args, &arg)?),
+                    value => value,
+                };
+                options.push((name.to_string(), value));
+            } else if let Some(short) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+                for (at, flag) in short.char_indices() {
+                    let name = long_name(flag).ok_or(format!("unknown option '-{flag}'"))?;
+                    if TAKES_VALUE.contains(&name) {
+                        let rest = &short[at + flag.len_utf8()..];
+                        let value = if rest.is_empty() {
+                            next_value(&mut args, &format!("-{flag}"))?
+                        } else {
+                            rest.to_string()
+                        };
+                        options.push((name.to_string(), Some(value)));
+                        break;
+                    }
+                    options.push((name.to_string(), None));
+                }
+            } else {
+                positional.push(arg);
+                continue;
+            }
+
+            for (name, value) in options {
+                match (name.as_str(), value) {
+                    ("ignore-case", None) => ignore_case = Some(true),
+                    ("case-sensitive", None) => ignore_case = Some(false),
+                    ("smart-case", None) => smart_case = true,
+                    ("locale", Some(value)) => config.locale = Locale::from_tag(&value),
+                    ("threads", Some(value)) => config.threads = parse_number(&name, &value)?,
+                    ("line-number", None) => config.line_number = true,
+                    ("count", None) => config.count = true,
+                    ("invert-match", None) => config.invert_match = true,
+                    ("word", None) => config.word = true,
+                    ("regex", None) => config.regex = true,
+                    ("all-match", None) => config.all_match = true,
+                    ("fuzzy", Some(value)) => config.fuzzy = Some(parse_number(&name, &value)?),
+                    ("pattern", Some(value)) => patterns.push(value),
+                    ("pattern-file", Some(value)) => {
+                        let contents =
+                            fs::read_to_string(&value).map_err(|e| MinigrepError::io(&value, e))?;
+                        patterns.extend(contents.lines().map(String::from));
+                        pattern_file = true;
+                    }
+                    ("json", None) => config.json = true,
+                    ("color", Some(value)) => config.color = ColorChoice::parse(&value)?,
+                    ("replace", Some(value)) => config.replace = Some(value),
+                    ("in-place", None) => config.in_place = true,
+                    ("dry-run", None) => config.dry_run = true,
+                    ("search-zip", None) => config.search_zip = true,
+                    ("index", None) => config.index = true,
+                    ("follow", None) => config.follow = true,
+                    ("encoding", Some(value)) => config.encoding = Some(Encoding::parse(&value)?),
+                    ("format", Some(value)) => config.format = Some(Format::parse(&value)?),
+                    ("field", Some(value)) => {
+                        if config.selector.is_some() {
+                            return Err(
+                                String::from("only one --field or --json-path is allowed").into()
+                            );
+                        }
+                        config.selector = Some(Selector::Column(value));
+                    }
+                    ("json-path", Some(value)) => {
+                        if config.selector.is_some() {
+                            return Err(
+                                String::from("only one --field or --json-path is allowed").into()
+                            );
+                        }
+                        config.selector = Some(Selector::JsonPath(JsonPath::parse(&value)?));
+                    }
+                    ("after-context", Some(value)) => {
+                        config.after_context = parse_number(&name, &value)?;
+                    }
+                    ("before-context", Some(value)) => {
+                        config.before_context = parse_number(&name, &value)?;
+                    }
+                    ("context", Some(value)) => {
+                        config.after_context = parse_number(&name, &value)?;
+                        config.before_context = config.after_context;
+                    }
+                    ("help", None) => return Ok(Command::Help),
+                    ("version", None) => return Ok(Command::Version),
+                    (_, Some(_)) if FLAGS.contains(&name.as_str()) => {
+                        return Err(format!("option '--{name}' doesn't take a value").into());
+                    }
+                    _ => return Err(format!("unknown option '--{name}'").into()),
+                }
+            }
+        }
+
+        if (config.in_place || config.dry_run) && config.replace.is_none() {
+            return Err(String::from("--in-place and --dry-run need --replace").into());
+        }
+        match (&config.selector, config.format) {
+            (Some(Selector::Column(_)), Some(Format::JsonLines)) => {
+                return Err(
+                    String::from("--field is for csv and tsv input; use --json-path").into(),
+                );
+            }
+            (Some(Selector::JsonPath(_)), Some(Format::Csv | Format::Tsv)) => {
+                return Err(String::from("--json-path is for jsonl input; use --field").into());
+            }
+            _ => {}
+        }
+        if config.in_place && config.search_zip {
+            return Err(String::from("--in-place can't edit compressed files").into());
+        }
+
+        // With -e or -f every positional argument is a path; otherwise the
+        // first one is the query.
+        let mut positional = positional.into_iter();
+        if patterns.is_empty() && !pattern_file {
+            match positional.next() {
+                Some(arg) => patterns.push(arg),
+                None => return Err(String::from("Didn't get a query string").into()),
+            }
+        }
+        if config.fuzzy.is_some() && config.regex {
+            return Err(String::from("--fuzzy can't be combined with --regex").into());
+        }
+        config.patterns = patterns;
+        config.paths = positional.collect();
+        if config.paths.is_empty() {
+            config.paths.push(String::from("-"));
+        }
+        if config.follow {
+            if config.paths.len() != 1 || config.paths[0] == "-" {
+                return Err(String::from("--follow needs exactly one file").into());
+            }
+            if config.count || config.replace.is_some() || config.search_zip {
+                return Err(String::from(
+                    "--follow can't be combined with --count, --replace or --search-zip",
+                )
+                .into());
+            }
+        }
+
+        if smart_case && ignore_case.is_none() {
+            let has_upper = config
+                .patterns
+                .iter()
+                .flat_map(|p| p.chars())
+                .any(char::is_uppercase);
+            ignore_case = Some(!has_upper);
+        }
+        config.ignore_case = ignore_case.unwrap_or_else(|| {
+            env::var("IGNORE_CASE").is_ok_and(|value| value == "true" || value == "1")
+        });
+
+        Ok(Command::Search(config))
+    }
+
+    /// Whether output lines need to say which input they came from.
+    pub fn show_path(&self) -> bool {
+        self.paths.len() > 1 || self.paths.iter().any(|path| Path::new(path).is_dir())
+    }
+
+    pub fn has_context(&self) -> bool {
+        self.before_context > 0 || self.after_context > 0
+    }
+
+    pub fn search_options(&self) -> SearchOptions {
+        SearchOptions {
+            before_context: self.before_context,
+            after_context: self.after_context,
+            invert_match: self.invert_match,
+        }
+    }
+
+    /// The kind of records the input called `path` holds, or `None` to
+    /// search it line by line.
+    pub fn record_format(&self, path: &str) -> Result<Option<Format>, String> {
+        match (self.format, &self.selector) {
+            (Some(format), _) => Ok(Some(format)),
+            (None, None) => Ok(None),
+            (None, Some(Selector::JsonPath(_))) => Ok(Some(Format::JsonLines)),
+            (None, Some(Selector::Column(_))) => match Format::from_path(path) {
+                Some(format) => Ok(Some(format)),
+                None => Err(format!("can't tell if {path} is CSV or TSV; use --format")),
+            },
+        }
+    }
+
+    /// Builds the matcher for all of the queries.
+    ///
+    /// Several literal queries share one Aho-Corasick automaton; several
+    /// regexes are joined into one alternation, so capture groups are
+    /// numbered across all of them.
+    pub fn matcher(&self) -> Result<Matcher, PatternError> {
+        if self.all_match {
+            let matchers = self
+                .patterns
+                .iter()
+                .map(|pattern| self.single_matcher(pattern))
+                .collect::<Result<_, _>>()?;
+            return Ok(Matcher::all(matchers));
+        }
+
+        let matcher = match self.patterns.as_slice() {
+            [pattern] => return self.single_matcher(pattern),
+            patterns if self.fuzzy.is_some() => {
+                let matchers = patterns
+                    .iter()
+                    .map(|pattern| self.single_matcher(pattern))
+                    .collect::<Result<_, _>>()?;
+                return Ok(Matcher::any(matchers));
+            }
+            patterns if self.regex => {
+                // Each pattern must stand on its own, or one like `a)|(?:b`
+                // could close its group early and change the others.
+                for pattern in patterns {
+                    Pattern::new(pattern)?;
+                }
+                let alternation: Vec<String> =
+                    patterns.iter().map(|p| format!("(?:{p})")).collect();
+                Matcher::regex(&alternation.join("|"), self.ignore_case)?
+            }
+            patterns => Matcher::literals(patterns, self.ignore_case, self.locale),
+        };
+        Ok(if self.word { matcher.word() } else { matcher })
+    }
+
+    fn single_matcher(&self, pattern: &str) -> Result<Matcher, PatternError> {
+        let matcher = if let Some(max_edits) = self.fuzzy {
+            Matcher::fuzzy(pattern, max_edits, self.ignore_case, self.locale)?
+        } else if self.regex {
+            Matcher::regex(pattern, self.ignore_case)?
+        } else if self.ignore_case {
+            Matcher::case_insensitive_in(pattern, self.locale)
+        } else {
+            Matcher::literal(pattern)
+        };
+        Ok(if self.word { matcher.word() } else { matcher })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(args: &[&str]) -> Result<Command, String> {
+        let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+        Config::build(args).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn combined_short_flags_and_double_dash() {
+        let Ok(Command::Search(config)) = build(&["-nvi", "--count", "--", "-w", "a.txt"]) else {
+            panic!("expected a search");
+        };
+        assert_eq!(vec!["-w"], config.patterns);
+        assert_eq!(vec!["a.txt"], config.paths);
+        assert!(config.line_number && config.invert_match && config.ignore_case && config.count);
+        assert!(!config.word);
+    }
+
+    #[test]
+    fn smart_case_and_locale() {
+        let Ok(Command::Search(config)) = build(&["-S", "--locale", "tr", "rust", "a.txt"]) else {
+            panic!("expected a search");
+        };
+        assert!(config.ignore_case);
+        assert_eq!(Locale::Turkic, config.locale);
+
+        let Ok(Command::Search(config)) = build(&["-S", "Rust", "a.txt"]) else {
+            panic!("expected a search");
+        };
+        assert!(!config.ignore_case);
+
+        let Ok(Command::Search(config)) = build(&["-S", "-i", "Rust", "a.txt"]) else {
+            panic!("expected a search");
+        };
+        assert!(config.ignore_case);
+    }
+
+    #[test]
+    fn color_choice() {
+        let Ok(Command::Search(config)) = build(&["--color=always", "foo", "a.txt"]) else {
+            panic!("expected a search");
+        };
+        assert_eq!(ColorChoice::Always, config.color);
+        assert!(build(&["--color", "sometimes", "foo"]).is_err());
+    }
+
+    #[test]
+    fn replace_options() {
+        let Ok(Command::Search(config)) = build(&["-r", "$1", "--dry-run", "(a)", "x"]) else {
+            panic!("expected a search");
+        };
+        assert_eq!(Some(String::from("$1")), config.replace);
+        assert!(config.dry_run);
+        assert_eq!(
+            Err(String::from("--in-place and --dry-run need --replace")),
+            build(&["--in-place", "a", "x"])
+        );
+    }
+
+    #[test]
+    fn repeated_and_file_patterns() {
+        let file = env::temp_dir().join(format!("minigrep-patterns-{}", std::process::id()));
+        fs::write(&file, "frog\nbog\n").unwrap();
+        let file_arg = file.display().to_string();
+
+        let Ok(Command::Search(config)) = build(&["-e", "you", "-f", &file_arg, "poem.txt"]) else {
+            panic!("expected a search");
+        };
+        assert_eq!(vec!["you", "frog", "bog"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+        fs::remove_file(&file).unwrap();
+
+        let matcher = config.matcher().unwrap();
+        assert!(matcher.is_match("To an admiring bog!"));
+        assert!(!matcher.is_match("How dreary to be somebody!"));
+    }
+
+    #[test]
+    fn fuzzy_queries_are_limited_after_case_folding() {
+        let query = "ß".repeat(40);
+        let Ok(Command::Search(config)) = build(&["--fuzzy", "1", &query, "a.txt"]) else {
+            panic!("expected a search");
+        };
+        assert!(config.matcher().is_ok());
+
+        let Ok(Command::Search(config)) = build(&["--fuzzy", "1", "-i", &query, "a.txt"]) else {
+            panic!("expected a search");
+        };
+        assert_eq!(
+            Some(PatternError::FuzzyQueryTooLong),
+            config.matcher().err()
+        );
+    }
+
+    #[test]
+    fn unreadable_pattern_files_are_io_errors() {
+        let args = ["minigrep", "-f", "/no/such/patterns", "a.txt"];
+        match Config::build(args.into_iter().map(String::from)) {
+            Err(MinigrepError::Io {
+                path: Some(path), ..
+            }) => assert_eq!("/no/such/patterns", path),
+            other => panic!("expected an I/O error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn regexes_are_checked_one_by_one() {
+        let Ok(Command::Search(config)) = build(&["--regex", "-e", "a)|(?:b", "-e", "c", "a.txt"])
+        else {
+            panic!("expected a search");
+        };
+        assert_eq!(
+            Some(PatternError::UnbalancedParen(1)),
+            config.matcher().err()
+        );
+    }
+
+    #[test]
+    fn context_values_inline_or_separate() {
+        let Ok(Command::Search(config)) = build(&["-nC2", "foo", "-A", "4", "a.txt"]) else {
+            panic!("expected a search");
+        };
+        assert_eq!((2, 4), (config.before_context, config.after_context));
+
+        let Ok(Command::Search(config)) = build(&["--before-context=3", "foo", "a.txt"]) else {
+            panic!("expected a search");
+        };
+        assert_eq!((3, 0), (config.before_context, config.after_context));
+    }
+
+    #[test]
+    fn help_version_and_usage_errors() {
+        assert_eq!(Ok(Command::Help), build(&["foo", "--help"]));
+        assert_eq!(Ok(Command::Version), build(&["--version"]));
+        assert_eq!(
+            Err(String::from("unknown option '-x'")),
+            build(&["-nx", "foo", "a.txt"])
+        );
+        assert_eq!(
+            Err(String::from("option '-C' requires a value")),
+            build(&["foo", "a.txt", "-C"])
+        );
+        assert_eq!(
+            Err(String::from("Didn't get a query string")),
+            build(&["-s"])
+        );
+    }
+
+    #[test]
+    fn follow_needs_one_file() {
+        let Ok(Command::Search(config)) = build(&["-F", "error", "app.log"]) else {
+            panic!("expected a search");
+        };
+        assert!(config.follow);
+        assert!(build(&["-F", "error"]).is_err());
+        assert!(build(&["-F", "error", "a.log", "b.log"]).is_err());
+        assert!(build(&["-Fc", "error", "app.log"]).is_err());
+    }
+
+    #[test]
+    fn index_subcommand() {
+        assert_eq!(
+            Ok(Command::Index(String::from("logs"))),
+            build(&["index", "logs"])
+        );
+        assert!(build(&["index"]).is_err());
+
+        let Ok(Command::Search(config)) = build(&["--index", "--", "index", "logs"]) else {
+            panic!("expected a search");
+        };
+        assert!(config.index);
+        assert_eq!(vec!["index"], config.patterns);
+    }
+}
@@ -0,0 +1,520 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+/// The compressed formats `--search-zip` recognizes, by their magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    pub fn detect(header: &[u8]) -> Option<Compression> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if header.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+}
+
+/// Wraps `reader` in a decoder if its first bytes show it is compressed,
+/// or hands it back untouched if not.
+///
+/// Only gzip can be decoded so far; zstd and bzip2 input is recognized but
+/// reported as unsupported rather than searched as binary noise.
+pub fn decompress<'a>(mut reader: impl BufRead + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+    match Compression::detect(reader.fill_buf()?) {
+        None => Ok(Box::new(reader)),
+        Some(Compression::Gzip) => Ok(Box::new(BufReader::new(GzDecoder::new(reader)))),
+        Some(format) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} decompression is not supported", format.name()),
+        )),
+    }
+}
+
+/// How far back a DEFLATE back-reference can reach.
+const WINDOW: usize = 32 * 1024;
+
+/// Streams the decompressed contents of a gzip file, including files made
+/// of several gzip members one after another.
+///
+/// Decoding happens as the output is read, so only the last 32 KiB of
+/// output is kept around for back-references, however large the file.
+pub struct GzDecoder<R> {
+    input: BitReader<R>,
+    state: State,
+    last_block: bool,
+    /// Output already handed out, kept for back-references, followed by
+    /// output not yet read.
+    window: Vec<u8>,
+    read: usize,
+    /// Checksum and length of the current member's output so far.
+    crc: u32,
+    size: u32,
+}
+
+enum State {
+    Header { first: bool },
+    BlockStart,
+    Stored(usize),
+    Codes(Box<(Huffman, Huffman)>),
+    Trailer,
+    Done,
+}
+
+impl<R: Read> GzDecoder<R> {
+    pub fn new(input: R) -> GzDecoder<R> {
+        GzDecoder {
+            input: BitReader::new(input),
+            state: State::Header { first: true },
+            last_block: false,
+            window: Vec::with_capacity(2 * WINDOW),
+            read: 0,
+            crc: 0,
+            size: 0,
+        }
+    }
+
+    /// Decodes the next piece of the stream, returning `false` at the end.
+    fn step(&mut self) -> io::Result<bool> {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::Header { first } => {
+                let Some(id) = self.input.try_byte()? else {
+                    if first {
+                        return Err(invalid("empty gzip stream"));
+                    }
+                    return Ok(false);
+                };
+                self.read_header(id)?;
+                self.last_block = false;
+                self.crc = 0;
+                self.size = 0;
+                self.state = State::BlockStart;
+            }
+            State::BlockStart if self.last_block => self.state = State::Trailer,
+            State::BlockStart => {
+                self.last_block = self.input.bits(1)? == 1;
+                self.state = match self.input.bits(2)? {
+                    0 => {
+                        self.input.align();
+                        let len = self.input.u16()?;
+                        if self.input.u16()? != !len {
+                            return Err(invalid("corrupt stored block length"));
+                        }
+                        State::Stored(len.into())
+                    }
+                    1 => State::Codes(Box::new(fixed_tables())),
+                    2 => State::Codes(Box::new(self.dynamic_tables()?)),
+                    _ => return Err(invalid("invalid block type")),
+                };
+            }
+            State::Stored(0) => self.state = State::BlockStart,
+            State::Stored(remaining) => {
+                let chunk = remaining.min(4096);
+                for _ in 0..chunk {
+                    let byte = self.input.byte()?;
+                    self.emit(byte);
+                }
+                self.state = State::Stored(remaining - chunk);
+            }
+            State::Codes(tables) => {
+                self.state = if self.inflate(&tables.0, &tables.1)? {
+                    State::BlockStart
+                } else {
+                    State::Codes(tables)
+                };
+            }
+            State::Trailer => {
+                self.input.align();
+                let crc = self.input.u32()?;
+                let size = self.input.u32()?;
+                if crc != self.crc || size != self.size {
+                    return Err(invalid("gzip checksum mismatch"));
+                }
+                self.state = State::Header { first: false };
+            }
+            State::Done => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Checks and skips a member header, whose first byte was `id`.
+    fn read_header(&mut self, id: u8) -> io::Result<()> {
+        const FHCRC: u8 = 0x02;
+        const FEXTRA: u8 = 0x04;
+        const FNAME: u8 = 0x08;
+        const FCOMMENT: u8 = 0x10;
+
+        if id != 0x1f || self.input.byte()? != 0x8b {
+            return Err(invalid("not in gzip format"));
+        }
+        if self.input.byte()? != 8 {
+            return Err(invalid("unknown gzip compression method"));
+        }
+        let flags = self.input.byte()?;
+        // Modification time, extra flags and operating system.
+        for _ in 0..6 {
+            self.input.byte()?;
+        }
+        if flags & FEXTRA != 0 {
+            for _ in 0..self.input.u16()? {
+                self.input.byte()?;
+            }
+        }
+        for field in [FNAME, FCOMMENT] {
+            if flags & field != 0 {
+                while self.input.byte()? != 0 {}
+            }
+        }
+        if flags & FHCRC != 0 {
+            self.input.u16()?;
+        }
+        Ok(())
+    }
+
+    /// Reads the code lengths a dynamic block starts with and builds its
+    /// literal/length and distance tables.
+    fn dynamic_tables(&mut self) -> io::Result<(Huffman, Huffman)> {
+        const ORDER: [usize; 19] = [
+            16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+        ];
+
+        let literals = self.input.bits(5)? as usize + 257;
+        let distances = self.input.bits(5)? as usize + 1;
+        let code_lengths = self.input.bits(4)? as usize + 4;
+
+        let mut lengths = [0; 19];
+        for &symbol in &ORDER[..code_lengths] {
+            lengths[symbol] = self.input.bits(3)? as u8;
+        }
+        let lengths_table = Huffman::new(&lengths)?;
+
+        let mut lengths = Vec::with_capacity(literals + distances);
+        while lengths.len() < literals + distances {
+            let (value, repeat) = match self.input.decode(&lengths_table)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => {
+                    let previous = *lengths
+                        .last()
+                        .ok_or_else(|| invalid("repeated code length with nothing before it"))?;
+                    (previous, 3 + self.input.bits(2)?)
+                }
+                17 => (0, 3 + self.input.bits(3)?),
+                _ => (0, 11 + self.input.bits(7)?),
+            };
+            lengths.extend(std::iter::repeat_n(value, repeat as usize));
+        }
+        if lengths.len() > literals + distances {
+            return Err(invalid("too many code lengths"));
+        }
+
+        let (literal_lengths, distance_lengths) = lengths.split_at(literals);
+        Ok((
+            Huffman::new(literal_lengths)?,
+            Huffman::new(distance_lengths)?,
+        ))
+    }
+
+    /// Decodes compressed symbols until the block ends, returning `true`,
+    /// or until enough output has built up for the next read.
+    fn inflate(&mut self, literals: &Huffman, distances: &Huffman) -> io::Result<bool> {
+        const LENGTH_BASE: [u16; 29] = [
+            3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99,
+            115, 131, 163, 195, 227, 258,
+        ];
+        const LENGTH_EXTRA: [u8; 29] = [
+            0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+        ];
+        const DISTANCE_BASE: [u16; 30] = [
+            1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025,
+            1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+        ];
+        const DISTANCE_EXTRA: [u8; 30] = [
+            0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12,
+            12, 13, 13,
+        ];
+
+        while self.window.len() - self.read < WINDOW {
+            let symbol = self.input.decode(literals)? as usize;
+            if symbol < 256 {
+                self.emit(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                return Ok(true);
+            }
+
+            let index = symbol - 257;
+            if index >= LENGTH_BASE.len() {
+                return Err(invalid("invalid length code"));
+            }
+            let length =
+                LENGTH_BASE[index] as usize + self.input.bits(LENGTH_EXTRA[index].into())? as usize;
+
+            let index = self.input.decode(distances)? as usize;
+            if index >= DISTANCE_BASE.len() {
+                return Err(invalid("invalid distance code"));
+            }
+            let distance = DISTANCE_BASE[index] as usize
+                + self.input.bits(DISTANCE_EXTRA[index].into())? as usize;
+            if distance > self.window.len() {
+                return Err(invalid("back-reference before the start of the output"));
+            }
+
+            for _ in 0..length {
+                let byte = self.window[self.window.len() - distance];
+                self.emit(byte);
+            }
+        }
+        Ok(false)
+    }
+
+    fn emit(&mut self, byte: u8) {
+        self.window.push(byte);
+        self.crc = crc32_update(self.crc, byte);
+        self.size = self.size.wrapping_add(1);
+    }
+}
+
+impl<R: Read> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.window.len() {
+            if !self.step()? {
+                return Ok(0);
+            }
+        }
+
+        let pending = &self.window[self.read..];
+        let len = pending.len().min(buf.len());
+        buf[..len].copy_from_slice(&pending[..len]);
+        self.read += len;
+
+        // Forget output that is both read and too far back to be referred to.
+        if self.read > 2 * WINDOW {
+            let forget = self.read - WINDOW;
+            self.window.drain(..forget);
+            self.read -= forget;
+        }
+        Ok(len)
+    }
+}
+
+/// Reads DEFLATE's bit stream, least significant bit first, a byte at a
+/// time so that nothing past the end of the stream is consumed.
+struct BitReader<R> {
+    inner: R,
+    bits: u32,
+    count: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    fn new(inner: R) -> BitReader<R> {
+        BitReader {
+            inner,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn try_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            return match self.inner.read(&mut byte) {
+                Ok(0) => Ok(None),
+                Ok(_) => Ok(Some(byte[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+        }
+    }
+
+    /// Reads a whole byte; only valid when aligned to a byte boundary.
+    fn byte(&mut self) -> io::Result<u8> {
+        self.try_byte()?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated gzip stream"))
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes([self.byte()?, self.byte()?]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from(self.u16()?) | (u32::from(self.u16()?) << 16))
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            self.bits |= u32::from(self.byte()?) << self.count;
+            self.count += 8;
+        }
+        let value = self.bits & ((1 << n) - 1);
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary.
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+
+    /// Reads one symbol, a bit at a time, using the canonical code's
+    /// property that codes of each length are consecutive numbers.
+    fn decode(&mut self, table: &Huffman) -> io::Result<u16> {
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for &count in &table.counts[1..] {
+            code |= self.bits(1)? as usize;
+            if code < first + count {
+                return Ok(table.symbols[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid Huffman code"))
+    }
+}
+
+/// A canonical Huffman code: how many codes there are of each length, and
+/// the symbols ordered by code.
+struct Huffman {
+    counts: [usize; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left: isize = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as isize;
+            if left < 0 {
+                return Err(invalid("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut symbols: Vec<(u8, u16)> = (0..)
+            .zip(lengths)
+            .filter(|&(_, &len)| len > 0)
+            .map(|(symbol, &len)| (len, symbol))
+            .collect();
+        symbols.sort_unstable();
+        Ok(Huffman {
+            counts,
+            symbols: symbols.into_iter().map(|(_, symbol)| symbol).collect(),
+        })
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literals = Huffman::new(&lengths).expect("the fixed code is complete");
+    let distances = Huffman::new(&[5; 30]).expect("the fixed code is valid");
+    (literals, distances)
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+};
+
+fn crc32_update(crc: u32, byte: u8) -> u32 {
+    let crc = !crc;
+    !(CRC32_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gzip_round_trip() {
+        let mut text = String::new();
+        decompress(&include_bytes!("../poem.txt.gz")[..])
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(include_str!("../poem.txt"), text);
+    }
+
+    #[test]
+    fn stored_blocks_and_several_members() {
+        let member: &[u8] = &[
+            31, 139, 8, 0, 0, 0, 0, 0, 4, 3, 1, 4, 0, 251, 255, 98, 111, 103, 10, 247, 120, 137,
+            57, 4, 0, 0, 0,
+        ];
+        let mut text = String::new();
+        GzDecoder::new([member, member].concat().as_slice())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!("bog\nbog\n", text);
+
+        let mut corrupt = member.to_vec();
+        corrupt[17] = b'x';
+        let error = GzDecoder::new(corrupt.as_slice())
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    }
+
+    #[test]
+    fn detects_formats_by_magic_bytes() {
+        assert_eq!(Some(Compression::Bzip2), Compression::detect(b"BZh91AY&SY"));
+        let Err(error) = decompress(&[0x28, 0xb5, 0x2f, 0xfd, 0][..]) else {
+            panic!("zstd should be unsupported");
+        };
+        assert_eq!(io::ErrorKind::Unsupported, error.kind());
+
+        let mut text = String::new();
+        decompress("plain text".as_bytes())
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!("plain text", text);
+    }
+}
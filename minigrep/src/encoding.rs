@@ -0,0 +1,202 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Text encodings minigrep can read besides UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    /// ISO 8859-1, where every byte is the code point of the same value.
+    Latin1,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    pub fn parse(value: &str) -> Result<Encoding, String> {
+        match value.to_ascii_lowercase().replace('-', "").as_str() {
+            "utf8" => Ok(Encoding::Utf8),
+            "latin1" | "iso88591" => Ok(Encoding::Latin1),
+            "utf16le" => Ok(Encoding::Utf16Le),
+            "utf16be" => Ok(Encoding::Utf16Be),
+            _ => Err(format!(
+                "invalid value '{value}' for '--encoding' \
+                 (expected utf8, latin1, utf16le or utf16be)"
+            )),
+        }
+    }
+
+    /// Recognizes a byte order mark, returning the encoding it stands for
+    /// and its length.
+    pub fn from_bom(header: &[u8]) -> Option<(Encoding, usize)> {
+        if header.starts_with(&[0xef, 0xbb, 0xbf]) {
+            Some((Encoding::Utf8, 3))
+        } else if header.starts_with(&[0xff, 0xfe]) {
+            Some((Encoding::Utf16Le, 2))
+        } else if header.starts_with(&[0xfe, 0xff]) {
+            Some((Encoding::Utf16Be, 2))
+        } else {
+            None
+        }
+    }
+}
+
+/// Wraps `reader` so that it produces UTF-8, converting from `encoding`,
+/// or from whatever its byte order mark says if `encoding` is `None`.
+/// Input without a byte order mark is taken to be UTF-8 already.
+///
+/// A byte order mark is dropped rather than searched.
+pub fn decode<'a>(
+    mut reader: impl BufRead + 'a,
+    encoding: Option<Encoding>,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    let bom = Encoding::from_bom(reader.fill_buf()?);
+    if let Some((found, len)) = bom
+        && encoding.is_none_or(|encoding| encoding == found)
+    {
+        reader.consume(len);
+    }
+
+    match encoding.or(bom.map(|(found, _)| found)) {
+        None | Some(Encoding::Utf8) => Ok(Box::new(reader)),
+        Some(encoding) => Ok(Box::new(BufReader::new(Transcoder {
+            inner: reader,
+            encoding,
+            pending: Vec::new(),
+            out: Vec::new(),
+            read: 0,
+        }))),
+    }
+}
+
+/// Converts text in another encoding to UTF-8 as it is read. Anything that
+/// can't be decoded becomes U+FFFD.
+struct Transcoder<R> {
+    inner: R,
+    encoding: Encoding,
+    /// Bytes that start a character the next chunk will finish.
+    pending: Vec<u8>,
+    out: Vec<u8>,
+    read: usize,
+}
+
+impl<R: BufRead> Transcoder<R> {
+    /// Decodes the next chunk of input into `out`. Returns `false` at the
+    /// end of the input.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.out.clear();
+        self.read = 0;
+
+        let chunk = self.inner.fill_buf()?;
+        if chunk.is_empty() {
+            if self.pending.is_empty() {
+                return Ok(false);
+            }
+            // A character cut off by the end of the input.
+            self.pending.clear();
+            self.out.extend_from_slice("\u{fffd}".as_bytes());
+            return Ok(true);
+        }
+
+        let mut text = String::new();
+        match self.encoding {
+            Encoding::Utf8 => unreachable!("UTF-8 input is read as it is"),
+            Encoding::Latin1 => text.extend(chunk.iter().map(|&b| char::from(b))),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                self.pending.extend_from_slice(chunk);
+                let mut units: Vec<u16> = self
+                    .pending
+                    .chunks_exact(2)
+                    .map(|pair| match self.encoding {
+                        Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                        _ => u16::from_be_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                // Hold back a leading surrogate until its partner arrives.
+                let mut keep = self.pending.len() % 2;
+                if units
+                    .last()
+                    .is_some_and(|unit| (0xd800..0xdc00).contains(unit))
+                {
+                    units.pop();
+                    keep += 2;
+                }
+                text.extend(
+                    char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+                );
+                self.pending.drain(..self.pending.len() - keep);
+            }
+        }
+        let len = chunk.len();
+        self.inner.consume(len);
+        self.out = text.into_bytes();
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for Transcoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.out.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let available = &self.out[self.read..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.read += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(bytes: &[u8], encoding: Option<Encoding>) -> String {
+        let mut text = String::new();
+        decode(bytes, encoding)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let mut utf16 = vec![0xff, 0xfe];
+        for unit in "naïve 🦀\n".encode_utf16() {
+            utf16.extend(unit.to_le_bytes());
+        }
+        assert_eq!("naïve 🦀\n", decoded(&utf16, None));
+
+        let big_endian: Vec<u8> = [0xfe, 0xff, 0, b'o', 0, b'k'].to_vec();
+        assert_eq!("ok", decoded(&big_endian, None));
+        assert_eq!("plain", decoded("\u{feff}plain".as_bytes(), None));
+    }
+
+    #[test]
+    fn forced_encodings() {
+        assert_eq!("café", decoded(b"caf\xe9", Some(Encoding::Latin1)));
+        assert_eq!(
+            "hi\u{fffd}",
+            decoded(&[b'h', 0, b'i', 0, b'!'], Some(Encoding::Utf16Le))
+        );
+        assert!(Encoding::parse("UTF-16LE").is_ok());
+        assert!(Encoding::parse("ebcdic").is_err());
+    }
+
+    #[test]
+    fn surrogates_split_across_reads() {
+        let mut bytes = Vec::new();
+        for unit in "a🦀b".encode_utf16() {
+            bytes.extend(unit.to_be_bytes());
+        }
+        // A reader that hands out three bytes at a time splits the crab.
+        let reader = BufReader::with_capacity(3, bytes.as_slice());
+        let mut text = String::new();
+        decode(reader, Some(Encoding::Utf16Be))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!("a🦀b", text);
+    }
+}
@@ -0,0 +1,107 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
+
+use crate::pattern::PatternError;
+
+/// Everything that can stop minigrep from searching, or from searching one
+/// of its inputs.
+#[derive(Debug)]
+pub enum MinigrepError {
+    /// The command line could not be understood.
+    BadArgs(String),
+    /// Reading or writing failed; `path` is the input involved, if any.
+    Io {
+        path: Option<String>,
+        source: io::Error,
+    },
+    /// An input that was read as text is not valid UTF-8.
+    InvalidUtf8 {
+        path: String,
+        source: Utf8Error,
+    },
+    BadPattern(PatternError),
+}
+
+impl MinigrepError {
+    /// Attaches the input `path` to an I/O error, telling apart the ones
+    /// that mean the input was not valid UTF-8.
+    pub fn io(path: &str, source: io::Error) -> MinigrepError {
+        let utf8 = source
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<Utf8Error>())
+            .copied();
+        match utf8 {
+            Some(source) => MinigrepError::InvalidUtf8 {
+                path: path.to_string(),
+                source,
+            },
+            None => MinigrepError::Io {
+                path: Some(path.to_string()),
+                source,
+            },
+        }
+    }
+}
+
+impl fmt::Display for MinigrepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MinigrepError::BadArgs(message) => f.write_str(message),
+            MinigrepError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{path}: {source}"),
+            MinigrepError::Io { path: None, source } => write!(f, "{source}"),
+            MinigrepError::InvalidUtf8 { path, .. } => write!(f, "{path}: not valid UTF-8"),
+            MinigrepError::BadPattern(e) => write!(f, "invalid pattern: {e}"),
+        }
+    }
+}
+
+impl Error for MinigrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MinigrepError::BadArgs(_) => None,
+            MinigrepError::Io { source, .. } => Some(source),
+            MinigrepError::InvalidUtf8 { source, .. } => Some(source),
+            MinigrepError::BadPattern(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for MinigrepError {
+    fn from(source: io::Error) -> MinigrepError {
+        MinigrepError::Io { path: None, source }
+    }
+}
+
+impl From<String> for MinigrepError {
+    fn from(message: String) -> MinigrepError {
+        MinigrepError::BadArgs(message)
+    }
+}
+
+impl From<PatternError> for MinigrepError {
+    fn from(e: PatternError) -> MinigrepError {
+        MinigrepError::BadPattern(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_errors_name_their_input() {
+        let error = MinigrepError::io("a.txt", io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!("a.txt: entity not found", error.to_string());
+
+        let bytes = vec![b'o', 0xff];
+        let utf8 = std::str::from_utf8(&bytes).unwrap_err();
+        let error = MinigrepError::io("b.txt", io::Error::new(io::ErrorKind::InvalidData, utf8));
+        assert!(matches!(error, MinigrepError::InvalidUtf8 { .. }));
+        assert_eq!("b.txt: not valid UTF-8", error.to_string());
+    }
+}
@@ -0,0 +1,84 @@
+/// Which language's case rules to use when folding.
+///
+/// Only Turkish and Azerbaijani differ from the default: there `I` folds to
+/// dotless `ı` and `İ` folds to a plain `i`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Locale {
+    #[default]
+    Root,
+    Turkic,
+}
+
+impl Locale {
+    /// Picks the case rules for a language tag such as `tr`, `az_AZ` or `en`.
+    pub fn from_tag(tag: &str) -> Locale {
+        let language = tag.split(['_', '-', '.']).next().unwrap_or(tag);
+        match language.to_ascii_lowercase().as_str() {
+            "tr" | "az" => Locale::Turkic,
+            _ => Locale::Root,
+        }
+    }
+}
+
+/// Folds `text` so that two strings which differ only in case fold to the
+/// same string. Unlike `to_lowercase`, this also equates `ß`, `ẞ` and `ss`,
+/// and the different forms of sigma.
+pub fn fold(text: &str, locale: Locale) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        push_folded(c, locale, &mut folded);
+    }
+    folded
+}
+
+/// Folds `text` and returns, for every byte of the folded string plus one
+/// past its end, the byte offset in `text` of the character it came from.
+pub(crate) fn fold_with_offsets(text: &str, locale: Locale) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len() + 1);
+    for (at, c) in text.char_indices() {
+        let before = folded.len();
+        push_folded(c, locale, &mut folded);
+        offsets.resize(offsets.len() + folded.len() - before, at);
+    }
+    offsets.push(text.len());
+    (folded, offsets)
+}
+
+fn push_folded(c: char, locale: Locale, out: &mut String) {
+    match (c, locale) {
+        ('I', Locale::Turkic) => out.push('ı'),
+        ('İ', Locale::Turkic) => out.push('i'),
+        ('ß' | 'ẞ', _) => out.push_str("ss"),
+        ('ς', _) => out.push('σ'),
+        ('ſ', _) => out.push('s'),
+        _ => out.extend(c.to_lowercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_beyond_lowercase() {
+        assert_eq!(fold("STRASSE", Locale::Root), fold("Straße", Locale::Root));
+        assert_eq!(fold("GROẞ", Locale::Root), fold("groß", Locale::Root));
+        assert_eq!(fold("ΟΔΟΣ", Locale::Root), fold("οδος", Locale::Root));
+    }
+
+    #[test]
+    fn turkic_dotted_and_dotless_i() {
+        assert_eq!("ıi", fold("Iİ", Locale::Turkic));
+        assert_eq!("ii\u{307}", fold("Iİ", Locale::Root));
+        assert_eq!(Locale::Turkic, Locale::from_tag("tr_TR.UTF-8"));
+        assert_eq!(Locale::Root, Locale::from_tag("en"));
+    }
+
+    #[test]
+    fn offsets_point_at_source_characters() {
+        let (folded, offsets) = fold_with_offsets("aßc", Locale::Root);
+        assert_eq!("assc", folded);
+        assert_eq!(vec![0, 1, 1, 3, 4], offsets);
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How often a followed file is checked for new data.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads what gets appended to a file, like `tail -f`, for as long as it
+/// is read from.
+///
+/// Reading starts at the end of the file as it is when opened. When there
+/// is nothing new, reads wait until there is, so the reader never reaches
+/// an end. Log rotation is followed: if the file shrinks it was truncated
+/// and is read again from the start, and if the path now names a different
+/// file the old one was renamed away and the new one is read from the
+/// start once the old one is finished.
+pub struct Follower {
+    path: PathBuf,
+    file: File,
+    position: u64,
+    identity: Option<(u64, u64)>,
+}
+
+impl Follower {
+    pub fn open(path: &Path) -> io::Result<Follower> {
+        let mut file = File::open(path)?;
+        let position = file.seek(SeekFrom::End(0))?;
+        let identity = identity(&file.metadata()?);
+        Ok(Follower {
+            path: path.to_path_buf(),
+            file,
+            position,
+            identity,
+        })
+    }
+
+    /// Reads whatever is available without waiting, returning 0 if there
+    /// is nothing new yet.
+    pub fn read_available(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.file.read(buf)?;
+        if read > 0 {
+            self.position += read as u64;
+            return Ok(read);
+        }
+
+        // At the end of the file we have open: see whether it was
+        // truncated, or replaced by a new file at the same path.
+        if self.file.metadata()?.len() < self.position {
+            self.position = self.file.seek(SeekFrom::Start(0))?;
+            return self.read_available(buf);
+        }
+        // The path may briefly not exist between a rename and the creation
+        // of the new file; keep the old one until it does.
+        if let Ok(metadata) = fs::metadata(&self.path)
+            && identity(&metadata) != self.identity
+        {
+            self.file = File::open(&self.path)?;
+            self.identity = identity(&self.file.metadata()?);
+            self.position = 0;
+            return self
+                .file
+                .read(buf)
+                .inspect(|&read| self.position = read as u64);
+        }
+        Ok(0)
+    }
+}
+
+impl Read for Follower {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.read_available(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// The device and inode of a file, which tell two files apart even if they
+/// had the same path.
+#[cfg(unix)]
+fn identity(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn identity(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::process;
+
+    fn available(follower: &mut Follower) -> String {
+        let mut buf = [0; 64];
+        let read = follower.read_available(&mut buf).unwrap();
+        String::from_utf8(buf[..read].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn follows_appends_truncation_and_rotation() {
+        let dir = std::env::temp_dir().join(format!("minigrep-follow-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "old line\n").unwrap();
+
+        let mut follower = Follower::open(&path).unwrap();
+        assert_eq!("", available(&mut follower));
+
+        let mut log = OpenOptions::new().append(true).open(&path).unwrap();
+        log.write_all(b"error: disk full\n").unwrap();
+        assert_eq!("error: disk full\n", available(&mut follower));
+
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!("new\n", available(&mut follower));
+
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"last words\n")
+            .unwrap();
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        fs::write(&path, "rotated\n").unwrap();
+        assert_eq!("last words\n", available(&mut follower));
+        assert_eq!("rotated\n", available(&mut follower));
+        assert_eq!("", available(&mut follower));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::pattern::PatternError;
+
+/// The longest query `Fuzzy` can look for: one bit per character of a `u64`.
+pub const MAX_QUERY_CHARS: usize = 64;
+
+/// Finds approximate occurrences of a query: places where the text can be
+/// turned into the query with at most `max_edits` single-character
+/// insertions, deletions or substitutions.
+///
+/// Uses Myers' bit-parallel algorithm, which keeps a whole column of the
+/// edit distance table in two machine words, so each character of text
+/// costs a handful of bit operations however long the query is.
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+    query: Vec<char>,
+    /// For each character, the positions in the query where it appears.
+    positions: HashMap<char, u64>,
+    max_edits: usize,
+}
+
+impl Fuzzy {
+    /// Fails if `query` is longer than `MAX_QUERY_CHARS` characters.
+    pub fn new(query: &str, max_edits: usize) -> Result<Fuzzy, PatternError> {
+        let query: Vec<char> = query.chars().collect();
+        if query.len() > MAX_QUERY_CHARS {
+            return Err(PatternError::FuzzyQueryTooLong);
+        }
+
+        let mut positions = HashMap::new();
+        for (i, &c) in query.iter().enumerate() {
+            *positions.entry(c).or_insert(0) |= 1 << i;
+        }
+        Ok(Fuzzy {
+            query,
+            positions,
+            max_edits,
+        })
+    }
+
+    /// Finds the first approximate match that starts at or after byte
+    /// `start`, returning it with the number of edits it needs.
+    ///
+    /// Of the places the match could end, the one needing the fewest edits
+    /// is taken, and of the places it could start, the nearest.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(Range<usize>, usize)> {
+        let len = self.query.len();
+        if len <= self.max_edits {
+            return Some((start..start, len));
+        }
+
+        let last = 1 << (len - 1);
+        let mut plus = u64::MAX;
+        let mut minus = 0;
+        let mut distance = len;
+        let mut best: Option<(usize, usize)> = None;
+
+        for (at, c) in text[start..].char_indices() {
+            let equal = self.positions.get(&c).copied().unwrap_or(0);
+            let vertical = equal | minus;
+            let horizontal = ((equal & plus).wrapping_add(plus) ^ plus) | equal;
+            let mut h_plus = minus | !(horizontal | plus);
+            let mut h_minus = plus & horizontal;
+            if h_plus & last != 0 {
+                distance += 1;
+            } else if h_minus & last != 0 {
+                distance -= 1;
+            }
+            // Shifting in zeros rather than ones lets a match start anywhere.
+            h_plus <<= 1;
+            h_minus <<= 1;
+            plus = h_minus | !(vertical | h_plus);
+            minus = h_plus & vertical;
+
+            // Once a match is found, keep going only while it gets better.
+            if best.is_some_and(|(_, edits)| distance >= edits) {
+                break;
+            }
+            if distance <= self.max_edits {
+                best = Some((start + at + c.len_utf8(), distance));
+            }
+        }
+
+        let (end, edits) = best?;
+        Some((self.start_of(text, start, end, edits)..end, edits))
+    }
+
+    /// Walks back from `end` to the nearest start at which the query is
+    /// `edits` edits away from the text, filling in the edit distance table
+    /// one column per character of text.
+    fn start_of(&self, text: &str, start: usize, end: usize, edits: usize) -> usize {
+        let len = self.query.len();
+        let mut column: Vec<usize> = (0..=len).collect();
+        let mut found = end;
+
+        for (at, c) in text[start..end].char_indices().rev() {
+            let mut diagonal = column[0];
+            column[0] += 1;
+            for i in 1..=len {
+                let substitute = diagonal + usize::from(self.query[len - i] != c);
+                diagonal = column[i];
+                column[i] = substitute.min(column[i] + 1).min(column[i - 1] + 1);
+            }
+            if column[len] == edits {
+                found = start + at;
+                break;
+            }
+            if column[0] > len + edits {
+                break;
+            }
+        }
+        found
+    }
+
+    /// The number of edits between the query and `text` as a whole.
+    pub fn distance(&self, text: &str) -> usize {
+        let mut row: Vec<usize> = (0..=self.query.len()).collect();
+        for (j, c) in text.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = j + 1;
+            for i in 1..row.len() {
+                let substitute = diagonal + usize::from(self.query[i - 1] != c);
+                diagonal = row[i];
+                row[i] = substitute.min(row[i] + 1).min(row[i - 1] + 1);
+            }
+        }
+        row[self.query.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_typos_with_their_edit_counts() {
+        let fuzzy = Fuzzy::new("nobody", 1).unwrap();
+        assert_eq!(Some((4..10, 0)), fuzzy.find_at("I'm nobody!", 0));
+        assert_eq!(Some((4..9, 1)), fuzzy.find_at("I'm nobdy!", 0));
+        assert_eq!(Some((4..9, 1)), fuzzy.find_at("I'm nobodxy!", 0));
+        assert_eq!(None, fuzzy.find_at("I'm nbdy!", 0));
+        assert_eq!(2, fuzzy.distance("nbdy"));
+    }
+
+    #[test]
+    fn characters_not_bytes() {
+        let fuzzy = Fuzzy::new("Straße", 1).unwrap();
+        assert_eq!(Some((3..9, 1)), fuzzy.find_at("Zu Strase", 0));
+        assert_eq!(Some((0..7, 0)), fuzzy.find_at("Straße", 0));
+    }
+
+    #[test]
+    fn long_queries_are_refused() {
+        assert!(Fuzzy::new(&"a".repeat(MAX_QUERY_CHARS), 1).is_ok());
+        assert_eq!(
+            Some(PatternError::FuzzyQueryTooLong),
+            Fuzzy::new(&"a".repeat(MAX_QUERY_CHARS + 1), 1).err()
+        );
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::config::Config;
+use crate::decompress::Compression;
+use crate::encoding::Encoding;
+use crate::replace::temp_path_for;
+use crate::walk;
+
+/// The name of the index file `minigrep index DIR` writes into DIR.
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+const MAGIC: &[u8] = b"minigrep-index 1\n";
+
+/// How many leading bytes are checked for NUL when deciding a file is
+/// binary, as `walk::is_binary` does.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// Remembers which three-byte sequences occur in each file below a
+/// directory, so a search can rule out files that can't contain the query
+/// without reading them.
+///
+/// Letters are lowercased (ASCII only) before indexing, so the same index
+/// serves case-sensitive and case-insensitive searches.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Index {
+    /// Keyed by path relative to the indexed directory, with `/` separators.
+    files: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    modified: u64,
+    len: u64,
+    /// Sorted and deduplicated; `None` for files whose bytes say nothing
+    /// about their text, like compressed files, which are always searched.
+    trigrams: Option<Vec<u32>>,
+}
+
+/// What `minigrep index` did.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct UpdateStats {
+    pub files: usize,
+    /// Files that were new or had changed since the last update.
+    pub reindexed: usize,
+}
+
+/// The trigrams a file must contain for a search to possibly match in it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// The search can't be narrowed down; every file is a candidate.
+    Anything,
+    /// Files containing every trigram of at least one of these.
+    AnyOf(Vec<Vec<u32>>),
+    /// Files containing every trigram of all of these.
+    AllOf(Vec<Vec<u32>>),
+}
+
+impl Query {
+    /// Works out what `config`'s search requires of a file. Only literal,
+    /// case-sensitive queries of the raw UTF-8 text are narrowed down;
+    /// regexes, fuzzy searches, inverted matches, case-insensitive searches
+    /// and searches of decoded text or record fields search every file.
+    pub fn new(config: &Config) -> Query {
+        // Folding the text's case can turn bytes the index never saw into
+        // ones the query has: "Straße" matches "strasse", and the Kelvin
+        // sign matches "k".
+        if config.regex || config.fuzzy.is_some() || config.invert_match || config.ignore_case {
+            return Query::Anything;
+        }
+        // The index holds the files' bytes, but these search something
+        // else: text decoded from another encoding, or record fields with
+        // their escapes undone, where `caf\u00e9` is "café".
+        if config
+            .encoding
+            .is_some_and(|encoding| encoding != Encoding::Utf8)
+            || config.format.is_some()
+            || config.selector.is_some()
+        {
+            return Query::Anything;
+        }
+
+        let required: Vec<Vec<u32>> = config
+            .patterns
+            .iter()
+            .map(|pattern| trigrams(pattern.as_bytes()))
+            .collect();
+        if config.all_match {
+            Query::AllOf(required)
+        } else if required.iter().any(Vec::is_empty) {
+            // A query shorter than three bytes could be anywhere.
+            Query::Anything
+        } else {
+            Query::AnyOf(required)
+        }
+    }
+
+    fn allows(&self, present: &[u32]) -> bool {
+        let contains_all = |needed: &Vec<u32>| {
+            needed
+                .iter()
+                .all(|trigram| present.binary_search(trigram).is_ok())
+        };
+        match self {
+            Query::Anything => true,
+            Query::AnyOf(alternatives) => alternatives.iter().any(contains_all),
+            Query::AllOf(required) => required.iter().all(contains_all),
+        }
+    }
+}
+
+impl Index {
+    /// Reads the index in `root`.
+    pub fn load(root: &Path) -> io::Result<Index> {
+        let path = root.join(INDEX_FILE);
+        let bytes = fs::read(&path).map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                let message = format!(
+                    "no index found; run 'minigrep index {}' first",
+                    root.display()
+                );
+                io::Error::new(e.kind(), message)
+            } else {
+                e
+            }
+        })?;
+        Index::decode(&bytes).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("corrupt index {}", path.display()),
+            )
+        })
+    }
+
+    /// Brings the index in `root` up to date, creating it if need be.
+    ///
+    /// Files whose size and modification time match the existing index
+    /// are not read again, and files that have gone are dropped. Directories
+    /// and files that can't be read are passed to `report` and left out;
+    /// searches still look in them.
+    pub fn update(
+        root: &Path,
+        report: &mut impl FnMut(&Path, io::Error),
+    ) -> io::Result<UpdateStats> {
+        let previous = Index::load(root).unwrap_or_default();
+        let mut index = Index::default();
+        let mut stats = UpdateStats::default();
+
+        for path in walk::walk(root, report) {
+            let Some(key) = key_for(root, &path) else {
+                continue;
+            };
+            if key == INDEX_FILE {
+                continue;
+            }
+            // A file that can't be read is left out, so searches still look
+            // in it and report the problem themselves.
+            let (modified, len) = match stamp(&path) {
+                Ok(stamp) => stamp,
+                Err(e) => {
+                    report(&path, e);
+                    continue;
+                }
+            };
+            let entry = match previous.files.get(&key) {
+                Some(entry) if entry.modified == modified && entry.len == len => entry.clone(),
+                _ => {
+                    let bytes = match fs::read(&path) {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            report(&path, e);
+                            continue;
+                        }
+                    };
+                    stats.reindexed += 1;
+                    let opaque = Compression::detect(&bytes).is_some()
+                        || bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0);
+                    Entry {
+                        modified,
+                        len,
+                        trigrams: (!opaque).then(|| trigrams(&bytes)),
+                    }
+                }
+            };
+            index.files.insert(key, entry);
+        }
+        stats.files = index.files.len();
+
+        // Written to the side and renamed into place, so a search running
+        // meanwhile never reads half an index.
+        let path = root.join(INDEX_FILE);
+        let temp = temp_path_for(&path);
+        fs::write(&temp, index.encode())?;
+        fs::rename(&temp, &path)?;
+        Ok(stats)
+    }
+
+    /// Keeps the files among `files`, all found below `root`, that might
+    /// match `query`. Files that are new or changed since the index was
+    /// updated are always kept.
+    pub fn filter(&self, root: &Path, files: Vec<PathBuf>, query: &Query) -> Vec<PathBuf> {
+        files
+            .into_iter()
+            .filter(|path| {
+                let Some(key) = key_for(root, path) else {
+                    return true;
+                };
+                if key == INDEX_FILE {
+                    return false;
+                }
+                if *query == Query::Anything {
+                    return true;
+                }
+                let Some(entry) = self.files.get(&key) else {
+                    return true;
+                };
+                if stamp(path).ok() != Some((entry.modified, entry.len)) {
+                    return true;
+                }
+                entry
+                    .trigrams
+                    .as_ref()
+                    .is_none_or(|present| query.allows(present))
+            })
+            .collect()
+    }
+
+    /// Serializes the index: a header line, then for each file its path,
+    /// modification time, length and trigrams, the last as gaps between
+    /// sorted values. Numbers are variable-length.
+    fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        push_number(&mut out, self.files.len() as u64);
+        for (key, entry) in &self.files {
+            push_number(&mut out, key.len() as u64);
+            out.extend_from_slice(key.as_bytes());
+            push_number(&mut out, entry.modified);
+            push_number(&mut out, entry.len);
+            match &entry.trigrams {
+                None => push_number(&mut out, 0),
+                Some(trigrams) => {
+                    push_number(&mut out, trigrams.len() as u64 + 1);
+                    let mut previous = 0;
+                    for &trigram in trigrams {
+                        push_number(&mut out, u64::from(trigram - previous));
+                        previous = trigram;
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Index> {
+        let mut input = bytes.strip_prefix(MAGIC)?;
+        let mut files = BTreeMap::new();
+        for _ in 0..take_number(&mut input)? {
+            let len = usize::try_from(take_number(&mut input)?).ok()?;
+            let key = String::from_utf8(input.get(..len)?.to_vec()).ok()?;
+            input = &input[len..];
+            let modified = take_number(&mut input)?;
+            let len = take_number(&mut input)?;
+            let trigrams = match take_number(&mut input)? {
+                0 => None,
+                count => {
+                    let mut trigrams = Vec::new();
+                    let mut previous = 0u32;
+                    for _ in 1..count {
+                        previous =
+                            previous.checked_add(take_number(&mut input)?.try_into().ok()?)?;
+                        trigrams.push(previous);
+                    }
+                    Some(trigrams)
+                }
+            };
+            files.insert(
+                key,
+                Entry {
+                    modified,
+                    len,
+                    trigrams,
+                },
+            );
+        }
+        input.is_empty().then_some(Index { files })
+    }
+}
+
+/// Every three-byte sequence in `text`, ASCII-lowercased, sorted.
+pub fn trigrams(text: &[u8]) -> Vec<u32> {
+    let mut trigrams: Vec<u32> = text
+        .windows(3)
+        .map(|w| {
+            let [a, b, c] = [w[0], w[1], w[2]].map(|byte| u32::from(byte.to_ascii_lowercase()));
+            a << 16 | b << 8 | c
+        })
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+fn key_for(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+/// A file's modification time in nanoseconds and its length, which
+/// together tell whether it changed since it was indexed.
+fn stamp(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64);
+    Ok((modified, metadata.len()))
+}
+
+fn push_number(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn take_number(input: &mut &[u8]) -> Option<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        n |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Command;
+    use std::process;
+
+    fn query(patterns: &[&str]) -> Query {
+        Query::new(&Config {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            ..Config::default()
+        })
+    }
+
+    fn fail(path: &Path, e: io::Error) {
+        panic!("{}: {e}", path.display());
+    }
+
+    #[test]
+    fn shortlists_files_and_notices_changes() {
+        let root = std::env::temp_dir().join(format!("minigrep-index-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("frog.txt"), "How public, like a Frog\n").unwrap();
+        fs::write(root.join("bog.txt"), "To an admiring bog!\n").unwrap();
+
+        let stats = Index::update(&root, &mut fail).unwrap();
+        assert_eq!(
+            UpdateStats {
+                files: 2,
+                reindexed: 2
+            },
+            stats
+        );
+        let stats = Index::update(&root, &mut fail).unwrap();
+        assert_eq!(
+            UpdateStats {
+                files: 2,
+                reindexed: 0
+            },
+            stats
+        );
+
+        let index = Index::load(&root).unwrap();
+        let files = walk::walk(&root, &mut fail);
+        let texts = vec![root.join("bog.txt"), root.join("frog.txt")];
+        assert_eq!(
+            vec![root.join("frog.txt")],
+            index.filter(&root, files.clone(), &query(&["FROG", "toad"]))
+        );
+        assert_eq!(texts, index.filter(&root, files.clone(), &query(&["og"])));
+
+        fs::write(root.join("bog.txt"), "To an admiring frog!\n").unwrap();
+        assert_eq!(
+            2,
+            index.filter(&root, files.clone(), &query(&["frog"])).len()
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn case_insensitive_searches_are_not_narrowed() {
+        let root = std::env::temp_dir().join(format!("minigrep-index-fold-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("street.txt"), "Hauptstraße 1\n").unwrap();
+        Index::update(&root, &mut fail).unwrap();
+
+        let index = Index::load(&root).unwrap();
+        let files = walk::walk(&root, &mut fail);
+        let query = Query::new(&Config {
+            patterns: vec!["strasse".to_string()],
+            ignore_case: true,
+            ..Config::default()
+        });
+        assert_eq!(
+            vec![root.join("street.txt")],
+            index.filter(&root, files, &query)
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn decoded_searches_are_not_narrowed() {
+        let root = std::env::temp_dir().join(format!("minigrep-index-latin1-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("menu.txt"), b"un caf\xe9\n").unwrap();
+        Index::update(&root, &mut fail).unwrap();
+
+        let index = Index::load(&root).unwrap();
+        let files = walk::walk(&root, &mut fail);
+        let Ok(Command::Search(config)) = Config::build(
+            ["minigrep", "--index", "--encoding", "latin1", "café"]
+                .into_iter()
+                .map(String::from),
+        ) else {
+            panic!("expected a search");
+        };
+        assert_eq!(
+            vec![root.join("menu.txt")],
+            index.filter(&root, files, &Query::new(&config))
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_files_are_reported_and_left_out() {
+        let root = std::env::temp_dir().join(format!("minigrep-index-broken-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("frog.txt"), "frog\n").unwrap();
+        std::os::unix::fs::symlink(root.join("gone.txt"), root.join("dangling.txt")).unwrap();
+
+        let mut reported = Vec::new();
+        let stats = Index::update(&root, &mut |path, _| reported.push(path.to_path_buf())).unwrap();
+        assert_eq!(vec![root.join("dangling.txt")], reported);
+        assert_eq!(1, stats.files);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let mut index = Index::default();
+        index.files.insert(
+            String::from("a/b.txt"),
+            Entry {
+                modified: 1_700_000_000_123_456_789,
+                len: 42,
+                trigrams: Some(trigrams(b"Hello, hello")),
+            },
+        );
+        index.files.insert(
+            String::from("c.gz"),
+            Entry {
+                modified: 0,
+                len: 7,
+                trigrams: None,
+            },
+        );
+        assert_eq!(Some(index.clone()), Index::decode(&index.encode()));
+        assert_eq!(None, Index::decode(b"minigrep-index 1\n\x05"));
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt::Write;
+
+/// Builds one JSON object, field by field, for the `--json` output.
+pub struct Object {
+    text: String,
+}
+
+impl Object {
+    pub fn new() -> Object {
+        Object {
+            text: String::from("{"),
+        }
+    }
+
+    fn key(&mut self, key: &str) {
+        if self.text.len() > 1 {
+            self.text.push(',');
+        }
+        push_string(&mut self.text, key);
+        self.text.push(':');
+    }
+
+    pub fn string(mut self, key: &str, value: &str) -> Object {
+        self.key(key);
+        push_string(&mut self.text, value);
+        self
+    }
+
+    pub fn number(mut self, key: &str, value: u64) -> Object {
+        self.key(key);
+        write!(self.text, "{value}").unwrap();
+        self
+    }
+
+    pub fn array(mut self, key: &str, items: Vec<Object>) -> Object {
+        self.key(key);
+        self.text.push('[');
+        for (i, item) in items.into_iter().enumerate() {
+            if i > 0 {
+                self.text.push(',');
+            }
+            self.text.push_str(&item.finish());
+        }
+        self.text.push(']');
+        self
+    }
+
+    pub fn object(mut self, key: &str, value: Object) -> Object {
+        self.key(key);
+        self.text.push_str(&value.finish());
+        self
+    }
+
+    pub fn finish(mut self) -> String {
+        self.text.push('}');
+        self.text
+    }
+}
+
+impl Default for Object {
+    fn default() -> Object {
+        Object::new()
+    }
+}
+
+/// Appends `value` as a quoted JSON string.
+pub fn push_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_and_escaping() {
+        let line = Object::new()
+            .string("type", "match")
+            .number("line_number", 3)
+            .array(
+                "submatches",
+                vec![Object::new().string("text", "say \"hi\"\t\u{1}")],
+            )
+            .finish();
+        assert_eq!(
+            r#"{"type":"match","line_number":3,"submatches":[{"text":"say \"hi\"\t\u0001"}]}"#,
+            line
+        );
+    }
+}
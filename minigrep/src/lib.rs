@@ -1,3 +1,56 @@
+pub mod aho_corasick;
+pub mod config;
+pub mod decompress;
+pub mod encoding;
+pub mod error;
+pub mod fold;
+pub mod follow;
+pub mod fuzzy;
+pub mod index;
+pub mod json;
+pub mod matcher;
+pub mod pattern;
+pub mod pool;
+pub mod printer;
+pub mod reader;
+pub mod records;
+pub mod replace;
+pub mod walk;
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+pub use error::MinigrepError;
+pub use matcher::Matcher;
+pub use pattern::{Pattern, PatternError};
+pub use reader::search_reader;
+
+/// A line reported by `search_context` or `search_reader`, either because
+/// it was selected or because it is context around a selected line.
+///
+/// Lines borrow from the searched text when it is in memory and are owned
+/// when they were read from a stream.
+#[derive(Debug, PartialEq)]
+pub struct Match<'a> {
+    /// The 1-based line number.
+    pub line_number: usize,
+    /// The byte offset of the start of the line within the searched text.
+    pub byte_offset: usize,
+    pub line: Cow<'a, str>,
+    /// The byte ranges within `line` that matched the query.
+    pub matches: Vec<Range<usize>>,
+    pub is_context: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SearchOptions {
+    pub before_context: usize,
+    pub after_context: usize,
+    /// Select the lines that do not match instead.
+    pub invert_match: bool,
+}
+
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
     contents
         .lines()
@@ -5,11 +58,120 @@ pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
         .collect()
 }
 pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let query = fold::fold(query, fold::Locale::Root);
     contents
         .lines()
-        .filter(|line| line.to_lowercase().contains(query))
+        .filter(|line| fold::fold(line, fold::Locale::Root).contains(&query))
         .collect()
 }
+pub fn search_regex<'a>(pattern: &Pattern, contents: &'a str) -> Vec<&'a str> {
+    contents
+        .lines()
+        .filter(|line| pattern.is_match(line))
+        .collect()
+}
+/// Returns the lines that contain `query` give or take `max_edits`
+/// insertions, deletions or substitutions, each paired with the fewest
+/// edits any match on it needs. Fails if `query` is too long to look for.
+pub fn search_fuzzy<'a>(
+    query: &str,
+    contents: &'a str,
+    max_edits: usize,
+) -> Result<Vec<(&'a str, usize)>, PatternError> {
+    let matcher = Matcher::fuzzy(query, max_edits, false, fold::Locale::Root)?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| {
+            let edits = matcher
+                .find_iter(line)
+                .iter()
+                .filter_map(|found| matcher.edits(line, found))
+                .min()?;
+            Some((line, edits))
+        })
+        .collect())
+}
+/// Splits `contents` into lines without their terminators, paired with the
+/// byte offset each line starts at.
+fn lines_with_offsets(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    contents.split_inclusive('\n').scan(0, |offset, raw| {
+        let start = *offset;
+        *offset += raw.len();
+        let line = raw.strip_suffix('\n').unwrap_or(raw);
+        Some((start, line.strip_suffix('\r').unwrap_or(line)))
+    })
+}
+
+/// Searches `contents` like `search`, but also returns the requested number
+/// of context lines around each selected line, in order and without
+/// repeating lines shared by neighbouring matches.
+pub fn search_context<'a>(
+    matcher: &Matcher,
+    contents: &'a str,
+    options: &SearchOptions,
+) -> Vec<Match<'a>> {
+    let mut window = ContextWindow::new(options);
+    let mut results = VecDeque::new();
+    for (index, (byte_offset, line)) in lines_with_offsets(contents).enumerate() {
+        let candidate = Match {
+            line_number: index + 1,
+            byte_offset,
+            line: Cow::Borrowed(line),
+            matches: matcher.find_iter(line),
+            is_context: true,
+        };
+        window.push(candidate, &mut results);
+    }
+    results.into()
+}
+
+/// Decides which lines are selected and which are context as lines arrive
+/// one at a time, holding on to at most `before_context` earlier lines.
+pub(crate) struct ContextWindow<'a> {
+    options: SearchOptions,
+    before: VecDeque<Match<'a>>,
+    after_remaining: usize,
+}
+
+impl<'a> ContextWindow<'a> {
+    pub(crate) fn new(options: &SearchOptions) -> ContextWindow<'a> {
+        ContextWindow {
+            options: options.clone(),
+            before: VecDeque::with_capacity(options.before_context),
+            after_remaining: 0,
+        }
+    }
+
+    /// Whether a line with these matches would be reported or remembered,
+    /// so callers can skip copying lines that are about to be discarded.
+    pub(crate) fn wants(&self, matches: &[Range<usize>]) -> bool {
+        matches.is_empty() == self.options.invert_match
+            || self.after_remaining > 0
+            || self.options.before_context > 0
+    }
+
+    /// Takes the next line, with its matches filled in, and appends whatever
+    /// lines are now ready to be reported to `out`.
+    pub(crate) fn push(&mut self, mut line: Match<'a>, out: &mut VecDeque<Match<'a>>) {
+        if line.matches.is_empty() == self.options.invert_match {
+            out.extend(self.before.drain(..));
+            line.is_context = false;
+            out.push_back(line);
+            self.after_remaining = self.options.after_context;
+        } else if self.after_remaining > 0 {
+            line.is_context = true;
+            out.push_back(line);
+            self.after_remaining -= 1;
+        } else if self.options.before_context > 0 {
+            if self.before.len() == self.options.before_context {
+                self.before.pop_front();
+            }
+            line.is_context = true;
+            self.before.push_back(line);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -40,4 +202,64 @@ Trust me.";
             search_case_insensitive(query, contents)
         );
     }
+
+    #[test]
+    fn regex() {
+        let pattern = Pattern::new(r"^\w+:$|thr(ee|ow)").unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        assert_eq!(
+            vec!["Rust:", "Pick three."],
+            search_regex(&pattern, contents)
+        );
+    }
+
+    #[test]
+    fn fuzzy() {
+        let query = "prodcutive";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        assert_eq!(
+            vec![("safe, fast, productive.", 2)],
+            search_fuzzy(query, contents, 2).unwrap()
+        );
+        assert!(search_fuzzy(query, contents, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn context() {
+        let matcher = Matcher::literal("e");
+        let contents = "one\r\ntwo\nsix\nfive\nfour\nnine\n";
+        let options = SearchOptions {
+            before_context: 1,
+            after_context: 1,
+            ..SearchOptions::default()
+        };
+
+        let results = search_context(&matcher, contents, &options);
+        let summary: Vec<(usize, usize, &str, bool)> = results
+            .iter()
+            .map(|m| (m.line_number, m.byte_offset, m.line.as_ref(), m.is_context))
+            .collect();
+        assert_eq!(
+            vec![
+                (1, 0, "one", false),
+                (2, 5, "two", true),
+                (3, 9, "six", true),
+                (4, 13, "five", false),
+                (5, 18, "four", true),
+                (6, 23, "nine", false),
+            ],
+            summary
+        );
+        assert_eq!(vec![3..4], results[5].matches);
+    }
 }
@@ -1,63 +1,273 @@
-use minigrep::{search, search_case_insensitive};
+use minigrep::config::{Command, Config, USAGE, VERSION};
+use minigrep::decompress::{self, Compression};
+use minigrep::encoding;
+use minigrep::follow::Follower;
+use minigrep::index::{Index, Query};
+use minigrep::pool::ThreadPool;
+use minigrep::printer::{Printer, Summary};
+use minigrep::replace;
+use minigrep::walk;
+use minigrep::{Matcher, MinigrepError};
 use std::env;
-use std::error::Error;
-use std::fs;
-use std::process;
-
-fn main() {
-    let config = Config::build(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
-    });
-    println!("Searching for {}", config.query);
-    println!("In file {}", config.file_path);
-
-    if let Err(e) = run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::thread;
+
+/// Exit codes, as grep uses them.
+const EXIT_NO_MATCH: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+
+fn main() -> ExitCode {
+    let config = match Config::build(env::args()) {
+        Ok(Command::Search(config)) => config,
+        Ok(Command::Index(dir)) => {
+            let mut failed = false;
+            let mut report = |path: &Path, e| {
+                eprintln!(
+                    "minigrep: {}",
+                    MinigrepError::io(&path.display().to_string(), e)
+                );
+                failed = true;
+            };
+            return match Index::update(Path::new(&dir), &mut report) {
+                Ok(stats) => {
+                    println!(
+                        "indexed {} files in {dir}, {} of them new or changed",
+                        stats.files, stats.reindexed
+                    );
+                    if failed {
+                        ExitCode::from(EXIT_ERROR)
+                    } else {
+                        ExitCode::SUCCESS
+                    }
+                }
+                Err(e) => {
+                    eprintln!("minigrep: {}", MinigrepError::io(&dir, e));
+                    ExitCode::from(EXIT_ERROR)
+                }
+            };
+        }
+        Ok(Command::Help) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("{VERSION}");
+            return ExitCode::SUCCESS;
+        }
+        Err(err @ MinigrepError::BadArgs(_)) => {
+            eprintln!("Problem parsing arguments: {err}");
+            eprintln!("Try 'minigrep --help' for more information.");
+            return ExitCode::from(EXIT_ERROR);
+        }
+        Err(err) => {
+            eprintln!("minigrep: {err}");
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    // Like grep, any error makes the exit code 2, even if lines matched.
+    match run(config) {
+        Ok(Outcome { failed: true, .. }) => ExitCode::from(EXIT_ERROR),
+        Ok(Outcome { matched: true, .. }) => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::from(EXIT_NO_MATCH),
+        Err(e) => {
+            eprintln!("minigrep: {e}");
+            ExitCode::from(EXIT_ERROR)
+        }
     }
 }
 
-fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+/// How a search went, which decides the exit code.
+struct Outcome {
+    /// Whether any line was selected.
+    matched: bool,
+    /// Whether some input could not be searched; each failure has already
+    /// been reported.
+    failed: bool,
+}
+
+/// Something to search: a file, or standard input when `path` is `None`.
+struct Input {
+    path: Option<PathBuf>,
+    /// How the input is referred to in the output.
+    name: String,
+    /// Files found by walking a directory are skipped if they look binary.
+    skip_binary: bool,
+}
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
-    } else {
-        search(&config.query, &contents)
+fn run(config: Config) -> Result<Outcome, MinigrepError> {
+    let matcher = config.matcher()?;
+    if config.follow {
+        return follow(&config, &matcher);
+    }
+    let (inputs, mut failed) = collect_inputs(&config)?;
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
     };
-    for line in results {
-        println!("{line}");
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut summary = Summary::default();
+
+    if threads == 1 || inputs.len() < 2 {
+        let mut printer = Printer::new(&config, &matcher);
+        for input in &inputs {
+            match search_input(&mut printer, &mut out, input) {
+                Ok(matched) => summary.add(matched),
+                Err(e) => {
+                    eprintln!("minigrep: {}", MinigrepError::io(&input.name, e));
+                    failed = true;
+                }
+            }
+        }
+        printer.print_summary(&mut out, &summary)?;
+    } else {
+        let pool = ThreadPool::new(threads.min(inputs.len()));
+        let config = Arc::new(config);
+        let matcher = Arc::new(matcher);
+        let job_config = Arc::clone(&config);
+        let job_matcher = Arc::clone(&matcher);
+        // Kept for naming the input whose search panicked, if one does.
+        let names: Vec<String> = inputs.iter().map(|input| input.name.clone()).collect();
+        let results = pool.map_ordered(inputs, move |input| {
+            let mut printer = Printer::new(&job_config, &job_matcher);
+            let mut buffer = Vec::new();
+            let result = search_input(&mut printer, &mut buffer, &input);
+            (input, buffer, result)
+        });
+
+        let mut printer = Printer::new(&config, &matcher);
+        for (name, result) in names.iter().zip(results) {
+            // The panic itself has already been reported by the panic hook.
+            let Ok((input, buffer, result)) = result else {
+                eprintln!("minigrep: {name}: search failed unexpectedly");
+                failed = true;
+                continue;
+            };
+            match result {
+                Ok(matched) => summary.add(matched),
+                Err(e) => {
+                    eprintln!("minigrep: {}", MinigrepError::io(&input.name, e));
+                    failed = true;
+                }
+            }
+            if !buffer.is_empty() {
+                printer.start_group(&mut out)?;
+                out.write_all(&buffer)?;
+            }
+        }
+        printer.print_summary(&mut out, &summary)?;
     }
 
-    Ok(())
+    out.flush()?;
+    Ok(Outcome {
+        matched: summary.matched_lines > 0,
+        failed,
+    })
 }
 
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
+/// Searches lines as they are appended to the one file given, until
+/// interrupted. Output is flushed line by line so it shows up right away.
+fn follow(config: &Config, matcher: &Matcher) -> Result<Outcome, MinigrepError> {
+    let path = &config.paths[0];
+    let follower = Follower::open(Path::new(path)).map_err(|e| MinigrepError::io(path, e))?;
+    let mut out = LineWriter::new(io::stdout().lock());
+    let mut printer = Printer::new(config, matcher);
+    // Reading starts at the end of the file, past any byte order mark, so
+    // without --encoding what is appended is taken to be UTF-8.
+    let matched = encoding::decode(BufReader::new(follower), config.encoding)
+        .and_then(|reader| printer.print_matches(&mut out, path, reader))
+        .map_err(|e| MinigrepError::io(path, e))?;
+    Ok(Outcome {
+        matched: matched > 0,
+        failed: false,
+    })
 }
 
-impl Config {
-    fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next();
+/// Lists what to search, with whether some directory could not be read
+/// while walking; that has already been reported.
+fn collect_inputs(config: &Config) -> Result<(Vec<Input>, bool), MinigrepError> {
+    let mut inputs = Vec::new();
+    let mut failed = false;
+    let mut report = |path: &Path, e| {
+        eprintln!(
+            "minigrep: {}",
+            MinigrepError::io(&path.display().to_string(), e)
+        );
+        failed = true;
+    };
+    let query = Query::new(config);
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
-        };
+    for path in &config.paths {
+        if path == "-" {
+            inputs.push(Input {
+                path: None,
+                name: String::from("(standard input)"),
+                skip_binary: false,
+            });
+        } else if Path::new(path).is_dir() {
+            let root = Path::new(path);
+            let mut files = walk::walk(root, &mut report);
+            if config.index {
+                let index = Index::load(root).map_err(|e| MinigrepError::io(path, e))?;
+                files = index.filter(root, files, &query);
+            }
+            for file in files {
+                inputs.push(Input {
+                    name: file.display().to_string(),
+                    path: Some(file),
+                    skip_binary: true,
+                });
+            }
+        } else {
+            inputs.push(Input {
+                path: Some(PathBuf::from(path)),
+                name: path.clone(),
+                skip_binary: false,
+            });
+        }
+    }
+
+    Ok((inputs, failed))
+}
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file path"),
+fn search_input(printer: &mut Printer, out: &mut impl Write, input: &Input) -> io::Result<u64> {
+    let name = &input.name;
+    let config = printer.config();
+    if let Some(replacement) = printer.replacement()
+        && (config.in_place || config.dry_run)
+    {
+        let Some(path) = &input.path else {
+            return Err(io::Error::other("cannot edit standard input in place"));
         };
-        let ignore_case = env::var("IGNORE_CASE").unwrap_or(String::from("true"));
-        let ignore_case = ignore_case == "true";
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
-        })
+        if input.skip_binary && walk::is_binary(path)? {
+            return Ok(0);
+        }
+        return replace::rewrite_file(path, printer.matcher(), replacement, config.dry_run, out);
     }
+
+    let reader: Box<dyn BufRead> = match &input.path {
+        None if config.search_zip => decompress::decompress(io::stdin().lock())?,
+        None => Box::new(io::stdin().lock()),
+        Some(path) => {
+            let mut reader = BufReader::new(File::open(path)?);
+            // Compressed files always look binary, so judge them by their
+            // contents instead, as with files in an encoding we were told.
+            let compressed = config.search_zip && Compression::detect(reader.fill_buf()?).is_some();
+            let trusted = compressed || config.encoding.is_some();
+            if input.skip_binary && !trusted && walk::is_binary(path)? {
+                return Ok(0);
+            }
+            if compressed {
+                decompress::decompress(reader)?
+            } else {
+                Box::new(reader)
+            }
+        }
+    };
+    printer.print_matches(out, name, encoding::decode(reader, config.encoding)?)
 }
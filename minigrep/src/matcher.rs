@@ -0,0 +1,433 @@
+use std::cell::OnceCell;
+use std::ops::Range;
+
+use crate::aho_corasick::AhoCorasick;
+use crate::fold::{self, Locale};
+use crate::fuzzy::Fuzzy;
+use crate::pattern::{Pattern, PatternError, is_word_char};
+
+/// Finds occurrences of a query in a line, whichever way the query is meant
+/// to be interpreted.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    kind: Kind,
+    word: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Literal(String),
+    CaseInsensitive(String, Locale),
+    Regex(Pattern),
+    /// Any of several literals, case-folded when a locale is given.
+    Literals(AhoCorasick, Option<Locale>),
+    /// Within a number of edits of a query, case-folded when a locale is
+    /// given.
+    Fuzzy(Fuzzy, Option<Locale>),
+    /// Lines must match every one of these; matches come from all of them.
+    All(Vec<Matcher>),
+    /// Lines may match any of these.
+    Any(Vec<Matcher>),
+}
+
+impl Matcher {
+    pub fn literal(query: &str) -> Matcher {
+        Matcher {
+            kind: Kind::Literal(query.to_string()),
+            word: false,
+        }
+    }
+
+    pub fn case_insensitive(query: &str) -> Matcher {
+        Matcher::case_insensitive_in(query, Locale::Root)
+    }
+
+    /// Like `case_insensitive`, but folds case following `locale`'s rules.
+    pub fn case_insensitive_in(query: &str, locale: Locale) -> Matcher {
+        Matcher {
+            kind: Kind::CaseInsensitive(fold::fold(query, locale), locale),
+            word: false,
+        }
+    }
+
+    pub fn regex(pattern: &str, ignore_case: bool) -> Result<Matcher, PatternError> {
+        let pattern = if ignore_case {
+            Pattern::case_insensitive(pattern)?
+        } else {
+            Pattern::new(pattern)?
+        };
+        Ok(Matcher {
+            kind: Kind::Regex(pattern),
+            word: false,
+        })
+    }
+
+    /// Matches any of `queries` in a single pass, however many there are.
+    pub fn literals(queries: &[String], ignore_case: bool, locale: Locale) -> Matcher {
+        let kind = if ignore_case {
+            let folded: Vec<String> = queries.iter().map(|q| fold::fold(q, locale)).collect();
+            Kind::Literals(AhoCorasick::new(&folded), Some(locale))
+        } else {
+            Kind::Literals(AhoCorasick::new(queries), None)
+        };
+        Matcher { kind, word: false }
+    }
+
+    /// Matches text that at most `max_edits` insertions, deletions or
+    /// substitutions turn into `query`.
+    /// The query is limited to `fuzzy::MAX_QUERY_CHARS` characters once
+    /// its case is folded, which can make it longer.
+    pub fn fuzzy(
+        query: &str,
+        max_edits: usize,
+        ignore_case: bool,
+        locale: Locale,
+    ) -> Result<Matcher, PatternError> {
+        let kind = if ignore_case {
+            Kind::Fuzzy(
+                Fuzzy::new(&fold::fold(query, locale), max_edits)?,
+                Some(locale),
+            )
+        } else {
+            Kind::Fuzzy(Fuzzy::new(query, max_edits)?, None)
+        };
+        Ok(Matcher { kind, word: false })
+    }
+
+    /// Only matches lines that every one of `matchers` matches.
+    pub fn all(matchers: Vec<Matcher>) -> Matcher {
+        Matcher {
+            kind: Kind::All(matchers),
+            word: false,
+        }
+    }
+
+    /// Matches whatever any of `matchers` matches.
+    pub fn any(matchers: Vec<Matcher>) -> Matcher {
+        Matcher {
+            kind: Kind::Any(matchers),
+            word: false,
+        }
+    }
+
+    /// Only accept matches that are not preceded or followed by a word
+    /// character.
+    pub fn word(mut self) -> Matcher {
+        self.word = true;
+        self
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+
+    /// Finds the first match in `line` starting at or after byte `start`.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.find_in(&Line::new(line), start)
+    }
+
+    fn find_in(&self, line: &Line, start: usize) -> Option<Range<usize>> {
+        let mut start = start;
+        loop {
+            let found = self.find_candidate(line, start)?;
+            if !self.word || is_whole_word(line.text, &found) {
+                return Some(found);
+            }
+            // A shorter literal of the set may start at the same place and
+            // be a whole word, as "foo" is where "foobar" isn't.
+            if let Some(found) = self
+                .shorter_at(line, &found)
+                .into_iter()
+                .find(|found| is_whole_word(line.text, found))
+            {
+                return Some(found);
+            }
+            start = found.start + line.text[found.start..].chars().next()?.len_utf8();
+        }
+    }
+
+    /// Returns every non-overlapping match in `line`.
+    pub fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+        // Shared by every search of the line, so its case is folded once.
+        let line = &Line::new(line);
+        let text = line.text;
+        while start <= text.len() {
+            let Some(found) = self.find_in(line, start) else {
+                break;
+            };
+            start = if found.is_empty() {
+                found.end + text[found.end..].chars().next().map_or(1, char::len_utf8)
+            } else {
+                found.end
+            };
+            matches.push(found);
+        }
+        matches
+    }
+
+    /// Like `find_iter`, but also returns the span of each capturing group
+    /// after the whole match. Literal queries have no groups.
+    pub fn captures_iter(&self, line: &str) -> Vec<Vec<Option<Range<usize>>>> {
+        self.find_iter(line)
+            .into_iter()
+            .map(|found| match &self.kind {
+                Kind::Regex(pattern) => pattern
+                    .captures_at(line, found.start)
+                    .unwrap_or_else(|| vec![Some(found)]),
+                _ => vec![Some(found)],
+            })
+            .collect()
+    }
+
+    /// How many edits the match `found` in `line` needs, for fuzzy
+    /// matchers; other matchers only find exact matches and return `None`.
+    pub fn edits(&self, line: &str, found: &Range<usize>) -> Option<usize> {
+        match &self.kind {
+            Kind::Fuzzy(fuzzy, None) => Some(fuzzy.distance(&line[found.clone()])),
+            Kind::Fuzzy(fuzzy, Some(locale)) => {
+                Some(fuzzy.distance(&fold::fold(&line[found.clone()], *locale)))
+            }
+            Kind::All(matchers) | Kind::Any(matchers) => matchers
+                .iter()
+                .filter(|matcher| matcher.find_at(line, found.start).as_ref() == Some(found))
+                .find_map(|matcher| matcher.edits(line, found)),
+            _ => None,
+        }
+    }
+
+    /// The other matches of a set of literals that start where `found`
+    /// does and end before it, longest first.
+    fn shorter_at(&self, line: &Line, found: &Range<usize>) -> Vec<Range<usize>> {
+        match &self.kind {
+            Kind::Literals(automaton, None) => automaton
+                .prefix_lens(line.text.as_bytes(), found.start)
+                .into_iter()
+                .map(|len| found.start..found.start + len)
+                .filter(|shorter| shorter.end < found.end)
+                .collect(),
+            Kind::Literals(automaton, Some(locale)) => line.with_folded(*locale, |folded| {
+                let at = folded.position(found.start);
+                automaton
+                    .prefix_lens(folded.text.as_bytes(), at)
+                    .into_iter()
+                    .map(|len| folded.in_line(line.text, at..at + len))
+                    .filter(|shorter| shorter.end < found.end)
+                    .collect()
+            }),
+            _ => Vec::new(),
+        }
+    }
+
+    fn find_candidate(&self, line: &Line, start: usize) -> Option<Range<usize>> {
+        match &self.kind {
+            Kind::Literal(query) => line.text[start..]
+                .find(query.as_str())
+                .map(|at| start + at..start + at + query.len()),
+            Kind::CaseInsensitive(query, locale) => {
+                line.find_folded(start, *locale, |folded, at| {
+                    folded[at..]
+                        .find(query.as_str())
+                        .map(|found| at + found..at + found + query.len())
+                })
+            }
+            Kind::Regex(pattern) => pattern.find_at(line.text, start),
+            Kind::Literals(automaton, None) => automaton
+                .find_at(line.text.as_bytes(), start)
+                .map(|(_, found)| found),
+            Kind::Literals(automaton, Some(locale)) => {
+                line.find_folded(start, *locale, |folded, at| {
+                    automaton
+                        .find_at(folded.as_bytes(), at)
+                        .map(|(_, found)| found)
+                })
+            }
+            Kind::Fuzzy(fuzzy, None) => fuzzy.find_at(line.text, start).map(|(found, _)| found),
+            Kind::Fuzzy(fuzzy, Some(locale)) => line.find_folded(start, *locale, |folded, at| {
+                fuzzy.find_at(folded, at).map(|(found, _)| found)
+            }),
+            Kind::All(matchers) => {
+                if !matchers
+                    .iter()
+                    .all(|matcher| matcher.find_in(line, 0).is_some())
+                {
+                    return None;
+                }
+                leftmost(matchers, line, start)
+            }
+            Kind::Any(matchers) => leftmost(matchers, line, start),
+        }
+    }
+}
+
+/// The leftmost match of any of `matchers`, preferring the longest.
+fn leftmost(matchers: &[Matcher], line: &Line, start: usize) -> Option<Range<usize>> {
+    matchers
+        .iter()
+        .filter_map(|matcher| matcher.find_in(line, start))
+        .min_by_key(|found| (found.start, std::cmp::Reverse(found.end)))
+}
+
+/// A line being searched, along with its case-folded copy once a matcher
+/// has needed it, so that finding every match in the line folds it once.
+struct Line<'a> {
+    text: &'a str,
+    folded: OnceCell<Folded>,
+}
+
+struct Folded {
+    locale: Locale,
+    text: String,
+    /// The offset in the line of the character each folded byte came from,
+    /// plus one for the end.
+    offsets: Vec<usize>,
+}
+
+impl Line<'_> {
+    fn new(text: &str) -> Line<'_> {
+        Line {
+            text,
+            folded: OnceCell::new(),
+        }
+    }
+
+    /// Runs `find` over the line folded with `locale`, from the folded
+    /// offset where `start` falls, and maps the hit back to offsets in the
+    /// line since folding can change a character's length.
+    fn find_folded(
+        &self,
+        start: usize,
+        locale: Locale,
+        find: impl Fn(&str, usize) -> Option<Range<usize>>,
+    ) -> Option<Range<usize>> {
+        self.with_folded(locale, |folded| {
+            let found = find(&folded.text, folded.position(start))?;
+            Some(folded.in_line(self.text, found))
+        })
+    }
+
+    fn with_folded<R>(&self, locale: Locale, f: impl FnOnce(&Folded) -> R) -> R {
+        let fold = || {
+            let (text, offsets) = fold::fold_with_offsets(self.text, locale);
+            Folded {
+                locale,
+                text,
+                offsets,
+            }
+        };
+        // Matchers combined from one configuration share a locale, so
+        // another one only comes up in tests; it is just not cached.
+        match self.folded.get_or_init(fold) {
+            folded if folded.locale == locale => f(folded),
+            _ => f(&fold()),
+        }
+    }
+}
+
+impl Folded {
+    /// Where the folding of the line's character at `start` begins.
+    fn position(&self, start: usize) -> usize {
+        self.offsets.partition_point(|&at| at < start)
+    }
+
+    /// Maps `found` in the folded text back to offsets in `line`.
+    fn in_line(&self, line: &str, found: Range<usize>) -> Range<usize> {
+        let offsets = &self.offsets;
+        let Range { start: at, end } = found;
+        // A match that ends partway through the folding of one character,
+        // like "s" against "ß", covers that whole character.
+        let end = if end > at && offsets[end] == offsets[end - 1] {
+            let partial = offsets[end];
+            partial + line[partial..].chars().next().map_or(0, char::len_utf8)
+        } else {
+            offsets[end]
+        };
+        offsets[at]..end
+    }
+}
+
+fn is_whole_word(line: &str, found: &Range<usize>) -> bool {
+    let before = line[..found.start].chars().next_back();
+    let after = line[found.end..].chars().next();
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_words_only() {
+        let matcher = Matcher::literal("test").word();
+        assert_eq!(Some(12..16), matcher.find_at("testing the test", 0));
+        assert!(!matcher.is_match("attested"));
+    }
+
+    #[test]
+    fn case_insensitive_offsets_point_into_the_original_line() {
+        let matcher = Matcher::case_insensitive("DUCT");
+        assert_eq!(Some(4..8), matcher.find_at("İ, Duct tape", 0));
+
+        let matcher = Matcher::case_insensitive("STRAS");
+        assert_eq!(Some(0..6), matcher.find_at("Straße", 0));
+    }
+
+    #[test]
+    fn long_lines_are_folded_once() {
+        let line = "Straße ".repeat(20_000);
+        let matcher = Matcher::case_insensitive("STRASSE");
+        let matches = matcher.find_iter(&line);
+        assert_eq!(20_000, matches.len());
+        assert_eq!(Some(&(8..15)), matches.get(1));
+
+        let queries = vec![String::from("SS"), String::from("e")];
+        let matcher = Matcher::literals(&queries, true, Locale::Root);
+        assert_eq!(40_000, matcher.find_iter(&line).len());
+    }
+
+    #[test]
+    fn whole_words_among_literals_starting_together() {
+        let queries = vec![String::from("foo"), String::from("foo-bar")];
+        let matcher = Matcher::literals(&queries, false, Locale::Root).word();
+        assert_eq!(vec![0..3], matcher.find_iter("foo-barx"));
+        assert_eq!(vec![0..7], matcher.find_iter("foo-bar baz"));
+
+        let matcher = Matcher::literals(&queries, true, Locale::Root).word();
+        assert_eq!(vec![0..3, 9..12], matcher.find_iter("FOO-BARX Foo"));
+    }
+
+    #[test]
+    fn many_literals_and_all_of() {
+        let queries = vec![String::from("frog"), String::from("BOG")];
+        let matcher = Matcher::literals(&queries, true, Locale::Root);
+        assert_eq!(
+            vec![19..23, 29..32],
+            matcher.find_iter("How public, like a FROG / an bog!")
+        );
+
+        let matcher = Matcher::all(vec![Matcher::literal("an"), Matcher::literal("bog")]);
+        assert_eq!(vec![3..5, 6..9], matcher.find_iter("To an bog!"));
+        assert!(!matcher.is_match("To an admiring frog"));
+    }
+
+    #[test]
+    fn fuzzy_matches_report_their_edits() {
+        let matcher = Matcher::fuzzy("NOBODY", 1, true, Locale::Root).unwrap();
+        let line = "Are you nobdy, too?";
+        let found = matcher.find_at(line, 0).unwrap();
+        assert_eq!(8..13, found);
+        assert_eq!(Some(1), matcher.edits(line, &found));
+        assert_eq!(None, Matcher::literal("too").edits(line, &(15..18)));
+    }
+
+    #[test]
+    fn turkic_case_folding() {
+        let matcher = Matcher::case_insensitive_in("ISTANBUL", Locale::Turkic);
+        assert!(matcher.is_match("ıstanbul"));
+        assert!(!matcher.is_match("istanbul"));
+
+        let matcher = Matcher::case_insensitive_in("İZMİR", Locale::Turkic);
+        assert!(matcher.is_match("izmir"));
+    }
+}
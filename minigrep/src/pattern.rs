@@ -0,0 +1,938 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// The largest bound accepted in a counted repetition like `a{2,5}`.
+const MAX_REPEAT: u32 = 1000;
+
+/// The most instructions a pattern may compile to. Nested repetitions
+/// multiply, so `((a{1000}){1000}){1000}` would otherwise need a billion.
+const MAX_PROGRAM_LEN: usize = 100_000;
+
+/// How deeply groups and repetitions may nest. Parsing and compiling
+/// recurse once per level, so this keeps them well inside the stack.
+const MAX_NESTING: usize = 250;
+
+/// A compiled regular expression.
+///
+/// Supports literals, `.`, character classes (`[a-z]`, `[^0-9]`, `\d`, `\w`,
+/// `\s` and their negations), the anchors `^`, `$`, `\b` and `\B`,
+/// alternation with `|`, capturing `( )` and non-capturing `(?: )` groups,
+/// and the repetitions `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`, each of
+/// which can be made lazy with a trailing `?`.
+///
+/// Matching runs on a Pike VM, so it takes time linear in the length of the
+/// text and never backtracks.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    prog: Vec<Inst>,
+    groups: usize,
+    case_insensitive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternError {
+    UnexpectedEnd,
+    UnbalancedParen(usize),
+    UnclosedClass(usize),
+    InvalidRange(usize),
+    InvalidRepeat(usize),
+    InvalidGroup(usize),
+    NothingToRepeat(usize),
+    InvalidEscape(usize),
+    /// The pattern compiles to more than `MAX_PROGRAM_LEN` instructions.
+    TooLarge,
+    /// Groups or repetitions nest more than `MAX_NESTING` deep at this
+    /// position.
+    TooDeep(usize),
+    /// A fuzzy query, after any case folding, is longer than
+    /// `fuzzy::MAX_QUERY_CHARS` characters.
+    FuzzyQueryTooLong,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::UnexpectedEnd => write!(f, "unexpected end of pattern"),
+            PatternError::UnbalancedParen(at) => write!(f, "unbalanced parenthesis at {at}"),
+            PatternError::UnclosedClass(at) => write!(f, "unclosed character class at {at}"),
+            PatternError::InvalidRange(at) => write!(f, "invalid class range at {at}"),
+            PatternError::InvalidRepeat(at) => write!(f, "invalid repetition at {at}"),
+            PatternError::InvalidGroup(at) => write!(f, "unsupported group syntax at {at}"),
+            PatternError::NothingToRepeat(at) => write!(f, "nothing to repeat at {at}"),
+            PatternError::InvalidEscape(at) => write!(f, "invalid escape at {at}"),
+            PatternError::TooDeep(at) => write!(
+                f,
+                "pattern nests more than {MAX_NESTING} levels deep at {at}"
+            ),
+            PatternError::TooLarge => write!(
+                f,
+                "pattern is too large (over {MAX_PROGRAM_LEN} instructions once repetitions are expanded)"
+            ),
+            PatternError::FuzzyQueryTooLong => write!(
+                f,
+                "fuzzy queries are limited to {} characters",
+                crate::fuzzy::MAX_QUERY_CHARS
+            ),
+        }
+    }
+}
+
+impl Error for PatternError {}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Pattern, PatternError> {
+        Pattern::build(pattern, false)
+    }
+
+    /// Like `new`, but letters match regardless of their case.
+    pub fn case_insensitive(pattern: &str) -> Result<Pattern, PatternError> {
+        Pattern::build(pattern, true)
+    }
+
+    fn build(pattern: &str, case_insensitive: bool) -> Result<Pattern, PatternError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+            depth: 0,
+        };
+        let (ast, _) = parser.parse_alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(PatternError::UnbalancedParen(parser.pos));
+        }
+
+        let mut compiler = Compiler {
+            prog: Vec::new(),
+            case_insensitive,
+        };
+        compiler.emit(Inst::Save(0));
+        compiler.compile(&ast)?;
+        compiler.emit(Inst::Save(1));
+        compiler.emit(Inst::Match);
+
+        Ok(Pattern {
+            source: pattern.to_string(),
+            prog: compiler.prog,
+            groups: parser.groups,
+            case_insensitive,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// The number of capturing groups, not counting the whole match.
+    pub fn group_count(&self) -> usize {
+        self.groups
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        self.find_at(text, 0)
+    }
+
+    /// Finds the leftmost match starting at or after the byte offset `start`.
+    ///
+    /// Anchors still see the whole of `text`, so `^` only matches at 0.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<Range<usize>> {
+        let slots = self.exec(text, start)?;
+        Some(slots[0]?..slots[1]?)
+    }
+
+    /// Returns every non-overlapping match in `text`.
+    pub fn find_iter(&self, text: &str) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start <= text.len() {
+            let Some(found) = self.find_at(text, start) else {
+                break;
+            };
+            start = if found.is_empty() {
+                found.end + text[found.end..].chars().next().map_or(1, char::len_utf8)
+            } else {
+                found.end
+            };
+            matches.push(found);
+        }
+        matches
+    }
+
+    /// Returns the span of the whole match followed by the span of each
+    /// capturing group, or `None` for groups that did not participate.
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Vec<Option<Range<usize>>>> {
+        let slots = self.exec(text, start)?;
+        Some(
+            slots
+                .chunks(2)
+                .map(|pair| match pair {
+                    [Some(s), Some(e)] => Some(*s..*e),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    fn exec(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let size = self.prog.len();
+        let mut clist: Vec<Thread> = Vec::new();
+        let mut nlist: Vec<Thread> = Vec::new();
+        let mut cseen = vec![false; size];
+        let mut nseen = vec![false; size];
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            if matched.is_none() {
+                let mut slots = vec![None; 2 * (self.groups + 1)];
+                self.add_thread(&mut clist, &mut cseen, 0, &mut slots, text, pos);
+            }
+            if clist.is_empty() && matched.is_some() {
+                break;
+            }
+
+            let next = text[pos..].chars().next();
+            for thread in clist.iter_mut() {
+                match &self.prog[thread.pc] {
+                    Inst::Match => {
+                        matched = Some(std::mem::take(&mut thread.slots));
+                        break;
+                    }
+                    inst => {
+                        if let Some(c) = next
+                            && self.step(inst, c)
+                        {
+                            let end = pos + c.len_utf8();
+                            self.add_thread(
+                                &mut nlist,
+                                &mut nseen,
+                                thread.pc + 1,
+                                &mut thread.slots,
+                                text,
+                                end,
+                            );
+                        }
+                    }
+                }
+            }
+
+            let Some(c) = next else {
+                break;
+            };
+            pos += c.len_utf8();
+            std::mem::swap(&mut clist, &mut nlist);
+            std::mem::swap(&mut cseen, &mut nseen);
+            nlist.clear();
+            nseen.fill(false);
+        }
+
+        matched
+    }
+
+    fn add_thread(
+        &self,
+        list: &mut Vec<Thread>,
+        seen: &mut [bool],
+        pc: usize,
+        slots: &mut [Option<usize>],
+        text: &str,
+        pos: usize,
+    ) {
+        let mut stack = vec![Frame::Explore(pc)];
+        while let Some(frame) = stack.pop() {
+            let pc = match frame {
+                Frame::Explore(pc) => pc,
+                Frame::Restore(slot, old) => {
+                    slots[slot] = old;
+                    continue;
+                }
+            };
+            if seen[pc] {
+                continue;
+            }
+            seen[pc] = true;
+
+            match &self.prog[pc] {
+                Inst::Jmp(to) => stack.push(Frame::Explore(*to)),
+                Inst::Split(first, second) => {
+                    stack.push(Frame::Explore(*second));
+                    stack.push(Frame::Explore(*first));
+                }
+                Inst::Save(slot) => {
+                    stack.push(Frame::Restore(*slot, slots[*slot]));
+                    slots[*slot] = Some(pos);
+                    stack.push(Frame::Explore(pc + 1));
+                }
+                Inst::Assert(assertion) => {
+                    if assertion.holds(text, pos) {
+                        stack.push(Frame::Explore(pc + 1));
+                    }
+                }
+                _ => list.push(Thread {
+                    pc,
+                    slots: slots.to_vec(),
+                }),
+            }
+        }
+    }
+
+    fn step(&self, inst: &Inst, c: char) -> bool {
+        match inst {
+            Inst::Char(expected) => {
+                *expected == c
+                    || (self.case_insensitive && simple_fold(*expected) == simple_fold(c))
+            }
+            Inst::Any => c != '\n',
+            // Case-insensitive classes were folded when compiled, so their
+            // ranges hold both cases of each letter; folding `c` as well
+            // catches letters like the Kelvin sign that only fold one way.
+            Inst::Class(class) => {
+                let inside =
+                    class.includes(c) || (self.case_insensitive && class.includes(simple_fold(c)));
+                inside != class.negated
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn simple_fold(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+fn simple_upper(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) => u,
+        _ => c,
+    }
+}
+
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+struct Thread {
+    pc: usize,
+    slots: Vec<Option<usize>>,
+}
+
+enum Frame {
+    Explore(usize),
+    Restore(usize, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Assertion {
+    fn holds(self, text: &str, pos: usize) -> bool {
+        let at_boundary = || {
+            let before = text[..pos].chars().next_back().is_some_and(is_word_char);
+            let after = text[pos..].chars().next().is_some_and(is_word_char);
+            before != after
+        };
+        match self {
+            Assertion::Start => pos == 0,
+            Assertion::End => pos == text.len(),
+            Assertion::WordBoundary => at_boundary(),
+            Assertion::NotWordBoundary => !at_boundary(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+    /// Whether the class holds every word character, as `\b` and `-w` see
+    /// them, on top of its ranges.
+    word: bool,
+}
+
+impl Class {
+    /// Whether `c` is in the class, before any negation.
+    fn includes(&self, c: char) -> bool {
+        (self.word && is_word_char(c)) || self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi)
+    }
+
+    /// The class with the other case of every letter in its ranges added,
+    /// so that negating it leaves out both cases.
+    fn case_folded(&self) -> Class {
+        let mut chars = Vec::new();
+        for &(lo, hi) in &self.ranges {
+            for c in lo..=hi {
+                for other in [simple_fold(c), simple_upper(c)] {
+                    if other != c && !self.includes(other) {
+                        chars.push(other);
+                    }
+                }
+            }
+        }
+        chars.sort_unstable();
+        chars.dedup();
+
+        let mut ranges = self.ranges.clone();
+        ranges.extend(chars.into_iter().map(|c| (c, c)));
+        Class {
+            negated: self.negated,
+            ranges,
+            word: self.word,
+        }
+    }
+
+    fn digit(negated: bool) -> Class {
+        Class {
+            negated,
+            ranges: vec![('0', '9')],
+            word: false,
+        }
+    }
+
+    fn word(negated: bool) -> Class {
+        Class {
+            negated,
+            ranges: Vec::new(),
+            word: true,
+        }
+    }
+
+    fn space(negated: bool) -> Class {
+        Class {
+            negated,
+            ranges: vec![('\t', '\r'), (' ', ' ')],
+            word: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Literal(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    /// How many groups the parser is inside.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char, PatternError> {
+        let c = self.peek().ok_or(PatternError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parses the alternation starting here, along with how many groups and
+    /// repetitions nest inside it.
+    fn parse_alternation(&mut self) -> Result<(Node, usize), PatternError> {
+        let (first, mut height) = self.parse_concat()?;
+        let mut branches = vec![first];
+        while self.eat('|') {
+            let (branch, branch_height) = self.parse_concat()?;
+            height = height.max(branch_height);
+            branches.push(branch);
+        }
+        if branches.len() == 1 {
+            Ok((branches.pop().unwrap(), height))
+        } else {
+            Ok((Node::Alternate(branches), height))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<(Node, usize), PatternError> {
+        let mut items = Vec::new();
+        let mut height = 0;
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let (atom, atom_height) = self.parse_atom()?;
+            let (item, item_height) = self.parse_repeat(atom, atom_height)?;
+            height = height.max(item_height);
+            items.push(item);
+        }
+        match items.len() {
+            0 => Ok((Node::Empty, height)),
+            1 => Ok((items.pop().unwrap(), height)),
+            _ => Ok((Node::Concat(items), height)),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<(Node, usize), PatternError> {
+        let start = self.pos;
+        let node = match self.next()? {
+            '.' => Node::Any,
+            '^' => Node::Assert(Assertion::Start),
+            '$' => Node::Assert(Assertion::End),
+            '[' => self.parse_class(start)?,
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err(PatternError::InvalidGroup(start));
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                if self.depth == MAX_NESTING {
+                    return Err(PatternError::TooDeep(start));
+                }
+                self.depth += 1;
+                let (inner, height) = self.parse_alternation()?;
+                self.depth -= 1;
+                if !self.eat(')') {
+                    return Err(PatternError::UnbalancedParen(start));
+                }
+                if height == MAX_NESTING {
+                    return Err(PatternError::TooDeep(start));
+                }
+                return Ok((Node::Group(Box::new(inner), index), height + 1));
+            }
+            ')' => return Err(PatternError::UnbalancedParen(start)),
+            '*' | '+' | '?' => return Err(PatternError::NothingToRepeat(start)),
+            '{' if self.is_counted_repeat(start) => {
+                return Err(PatternError::NothingToRepeat(start));
+            }
+            '\\' => self.parse_escape(start)?,
+            c => Node::Literal(c),
+        };
+        Ok((node, 0))
+    }
+
+    fn parse_escape(&mut self, start: usize) -> Result<Node, PatternError> {
+        let node = match self.next()? {
+            'd' => Node::Class(Class::digit(false)),
+            'D' => Node::Class(Class::digit(true)),
+            'w' => Node::Class(Class::word(false)),
+            'W' => Node::Class(Class::word(true)),
+            's' => Node::Class(Class::space(false)),
+            'S' => Node::Class(Class::space(true)),
+            'b' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            c => Node::Literal(escaped_char(c).ok_or(PatternError::InvalidEscape(start))?),
+        };
+        Ok(node)
+    }
+
+    fn parse_class(&mut self, start: usize) -> Result<Node, PatternError> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut word = false;
+        let mut first = true;
+
+        loop {
+            let at = self.pos;
+            let c = self
+                .next()
+                .map_err(|_| PatternError::UnclosedClass(start))?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let lo = if c == '\\' {
+                match self
+                    .next()
+                    .map_err(|_| PatternError::UnclosedClass(start))?
+                {
+                    'd' => {
+                        ranges.extend(Class::digit(false).ranges);
+                        continue;
+                    }
+                    'w' => {
+                        word = true;
+                        continue;
+                    }
+                    's' => {
+                        ranges.extend(Class::space(false).ranges);
+                        continue;
+                    }
+                    e => escaped_char(e).ok_or(PatternError::InvalidEscape(at))?,
+                }
+            } else {
+                c
+            };
+
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let hi = match self.next()? {
+                    '\\' => {
+                        let e = self.next()?;
+                        escaped_char(e).ok_or(PatternError::InvalidEscape(self.pos - 2))?
+                    }
+                    hi => hi,
+                };
+                if hi < lo {
+                    return Err(PatternError::InvalidRange(at));
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+
+        Ok(Node::Class(Class {
+            negated,
+            ranges,
+            word,
+        }))
+    }
+
+    fn parse_repeat(
+        &mut self,
+        mut atom: Node,
+        mut height: usize,
+    ) -> Result<(Node, usize), PatternError> {
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') if self.is_counted_repeat(start) => self.parse_counts(start)?,
+                _ => return Ok((atom, height)),
+            };
+            if self.pos == start {
+                self.pos += 1;
+            }
+            if matches!(atom, Node::Assert(_) | Node::Empty) {
+                return Err(PatternError::NothingToRepeat(start));
+            }
+            if height == MAX_NESTING {
+                return Err(PatternError::TooDeep(start));
+            }
+            height += 1;
+            let greedy = !self.eat('?');
+            atom = Node::Repeat {
+                node: Box::new(atom),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    /// Whether the `{` at `at` starts a `{n}`, `{n,}` or `{n,m}` rather than
+    /// being a literal brace.
+    fn is_counted_repeat(&self, at: usize) -> bool {
+        let Some(len) = self.chars[at + 1..].iter().position(|&c| c == '}') else {
+            return false;
+        };
+        let body: String = self.chars[at + 1..at + 1 + len].iter().collect();
+        let (min, max) = body.split_once(',').unwrap_or((&body, ""));
+        !min.is_empty()
+            && min.chars().all(|c| c.is_ascii_digit())
+            && max.chars().all(|c| c.is_ascii_digit())
+    }
+
+    fn parse_counts(&mut self, start: usize) -> Result<(u32, Option<u32>), PatternError> {
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            match self.next()? {
+                '}' => break,
+                c => text.push(c),
+            }
+        }
+        let invalid = || PatternError::InvalidRepeat(start);
+        let (min, max) = match text.split_once(',') {
+            None => {
+                let n = text.parse().map_err(|_| invalid())?;
+                (n, Some(n))
+            }
+            Some((min, "")) => (min.parse().map_err(|_| invalid())?, None),
+            Some((min, max)) => (
+                min.parse().map_err(|_| invalid())?,
+                Some(max.parse().map_err(|_| invalid())?),
+            ),
+        };
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT || max < min) {
+            return Err(invalid());
+        }
+        Ok((min, max))
+    }
+}
+
+fn escaped_char(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        c if c.is_ascii_alphanumeric() => None,
+        c => Some(c),
+    }
+}
+
+struct Compiler {
+    prog: Vec<Inst>,
+    case_insensitive: bool,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> usize {
+        self.prog.push(inst);
+        self.prog.len() - 1
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), PatternError> {
+        // Every instruction comes from some call here, so checking on the
+        // way in stops runaway repetitions after at most a few more.
+        if self.prog.len() > MAX_PROGRAM_LEN {
+            return Err(PatternError::TooLarge);
+        }
+        match node {
+            Node::Empty => {}
+            Node::Literal(c) => {
+                self.emit(Inst::Char(*c));
+            }
+            Node::Any => {
+                self.emit(Inst::Any);
+            }
+            Node::Class(class) if self.case_insensitive => {
+                self.emit(Inst::Class(class.case_folded()));
+            }
+            Node::Class(class) => {
+                self.emit(Inst::Class(class.clone()));
+            }
+            Node::Assert(assertion) => {
+                self.emit(Inst::Assert(*assertion));
+            }
+            Node::Group(inner, index) => match index {
+                Some(index) => {
+                    self.emit(Inst::Save(2 * index));
+                    self.compile(inner)?;
+                    self.emit(Inst::Save(2 * index + 1));
+                }
+                None => self.compile(inner)?,
+            },
+            Node::Concat(items) => {
+                for item in items {
+                    self.compile(item)?;
+                }
+            }
+            Node::Alternate(branches) => {
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.emit(Inst::Split(0, 0));
+                        self.compile(branch)?;
+                        jumps.push(self.emit(Inst::Jmp(0)));
+                        let next = self.prog.len();
+                        self.prog[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.compile(branch)?;
+                    }
+                }
+                let end = self.prog.len();
+                for jump in jumps {
+                    self.prog[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.emit(Inst::Split(0, 0));
+                        self.compile(node)?;
+                        self.emit(Inst::Jmp(split));
+                        let out = self.prog.len();
+                        self.prog[split] = self.split(*greedy, split + 1, out);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0)));
+                            self.compile(node)?;
+                        }
+                        let out = self.prog.len();
+                        for split in splits {
+                            self.prog[split] = self.split(*greedy, split + 1, out);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn split(&self, greedy: bool, body: usize, out: usize) -> Inst {
+        if greedy {
+            Inst::Split(body, out)
+        } else {
+            Inst::Split(out, body)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_anchors_and_repetition() {
+        let pattern = Pattern::new(r"^[A-Z]\w+: \d{2,3}$").unwrap();
+        assert!(pattern.is_match("Status: 404"));
+        assert!(!pattern.is_match("status: 404"));
+        assert!(!pattern.is_match("Status: 4040"));
+    }
+
+    #[test]
+    fn leftmost_first_alternation_and_groups() {
+        let pattern = Pattern::new(r"(fo+|bar)(\.rs)?").unwrap();
+        assert_eq!(Some(4..12), pattern.find("see foooo.rs"));
+        assert_eq!(
+            Some(vec![Some(0..3), Some(0..3), None]),
+            pattern.captures_at("bar", 0)
+        );
+        assert_eq!(vec![0..3, 4..8], pattern.find_iter("bar fooo"));
+    }
+
+    #[test]
+    fn lazy_and_case_insensitive() {
+        let pattern = Pattern::new("<.+?>").unwrap();
+        assert_eq!(Some(0..3), pattern.find("<a><b>"));
+
+        let pattern = Pattern::case_insensitive(r"\bruSt\b").unwrap();
+        assert!(pattern.is_match("Rust:"));
+        assert!(!pattern.is_match("Trust me."));
+    }
+
+    #[test]
+    fn word_characters_agree_with_word_boundaries() {
+        let pattern = Pattern::new(r"^\w+$").unwrap();
+        assert!(pattern.is_match("café_1"));
+        assert!(!pattern.is_match("café-1"));
+
+        let pattern = Pattern::new(r"\bé\w").unwrap();
+        assert_eq!(Some(3..7), pattern.find("un ééa"));
+        assert!(!Pattern::new(r"\W").unwrap().is_match("é"));
+        assert_eq!(Some(0..5), Pattern::new(r"[\w-]+").unwrap().find("é-é"));
+    }
+
+    #[test]
+    fn negated_classes_exclude_both_cases() {
+        let pattern = Pattern::case_insensitive("^[^a]$").unwrap();
+        assert!(!pattern.is_match("a"));
+        assert!(!pattern.is_match("A"));
+        assert!(pattern.is_match("b"));
+
+        let pattern = Pattern::case_insensitive("^[^A-Z]+$").unwrap();
+        assert!(!pattern.is_match("rust"));
+        assert!(!pattern.is_match("RUST"));
+        assert!(pattern.is_match("2024"));
+
+        let pattern = Pattern::case_insensitive("^[k]$").unwrap();
+        assert!(pattern.is_match("K"));
+        assert!(pattern.is_match("\u{212a}"));
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        assert_eq!(
+            Err(PatternError::UnbalancedParen(0)),
+            Pattern::new("(ab").map(|_| ())
+        );
+        assert_eq!(
+            Err(PatternError::NothingToRepeat(0)),
+            Pattern::new("*a").map(|_| ())
+        );
+        assert_eq!(
+            Err(PatternError::UnclosedClass(1)),
+            Pattern::new("a[bc").map(|_| ())
+        );
+        assert_eq!(
+            Err(PatternError::TooLarge),
+            Pattern::new("((a{1000}){1000}){1000}").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn rejects_deeply_nested_patterns() {
+        let nested = |depth: usize| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Pattern::new(&nested(MAX_NESTING)).unwrap().is_match("a"));
+        assert_eq!(
+            Err(PatternError::TooDeep(MAX_NESTING)),
+            Pattern::new(&nested(5000)).map(|_| ())
+        );
+        assert_eq!(
+            Err(PatternError::TooDeep(MAX_NESTING + 1)),
+            Pattern::new(&format!("a{}", "*".repeat(5000))).map(|_| ())
+        );
+        let stacked = format!("(a{}){}", "*".repeat(200), "*".repeat(200));
+        assert!(matches!(
+            Pattern::new(&stacked),
+            Err(PatternError::TooDeep(_))
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+/// Worker threads that search several inputs at once for `-j`/`--threads`.
+///
+/// Jobs only go in through `map_ordered`, so each input's output can be
+/// buffered by its worker and printed in the order the inputs were given.
+/// Workers never print anything themselves.
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+impl ThreadPool {
+    /// Starts `size` workers. Callers cap this at the number of inputs, so
+    /// no thread is started that would sit idle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn new(size: usize) -> ThreadPool {
+        assert!(size > 0);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut workers = Vec::with_capacity(size);
+
+        for _ in 0..size {
+            workers.push(Worker::new(Arc::clone(&receiver)));
+        }
+
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+
+    fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = Box::new(f);
+
+        self.sender.as_ref().unwrap().send(job).unwrap();
+    }
+
+    /// Runs `f` on every item on the pool and returns the results in the
+    /// same order as `items`, each as soon as it and all the ones before it
+    /// are done. A call to `f` that panics gives an `Err` holding the panic
+    /// instead of a result; the other items are still run.
+    pub fn map_ordered<T, R, F>(&self, items: Vec<T>, f: F) -> Ordered<R>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let f = Arc::new(f);
+        let total = items.len();
+
+        for (index, item) in items.into_iter().enumerate() {
+            let sender = sender.clone();
+            let f = Arc::clone(&f);
+            self.execute(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| f(item)));
+                // The receiver only goes away if the caller stopped reading.
+                let _ = sender.send((index, result));
+            });
+        }
+
+        Ordered {
+            receiver,
+            pending: BTreeMap::new(),
+            next: 0,
+            total,
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            worker.thread.join().unwrap();
+        }
+    }
+}
+
+struct Worker {
+    thread: thread::JoinHandle<()>,
+}
+
+impl Worker {
+    fn new(receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+        let thread = thread::spawn(move || {
+            loop {
+                let message = receiver.lock().unwrap().recv();
+
+                match message {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Worker { thread }
+    }
+}
+
+/// The results of `ThreadPool::map_ordered`, holding back any that finish
+/// early until their turn comes.
+pub struct Ordered<R> {
+    receiver: mpsc::Receiver<(usize, thread::Result<R>)>,
+    pending: BTreeMap<usize, thread::Result<R>>,
+    next: usize,
+    total: usize,
+}
+
+impl<R> Iterator for Ordered<R> {
+    type Item = thread::Result<R>;
+
+    fn next(&mut self) -> Option<thread::Result<R>> {
+        if self.next == self.total {
+            return None;
+        }
+        while !self.pending.contains_key(&self.next) {
+            let (index, result) = self.receiver.recv().ok()?;
+            self.pending.insert(index, result);
+        }
+        let result = self.pending.remove(&self.next);
+        self.next += 1;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn results_keep_input_order() {
+        let pool = ThreadPool::new(4);
+        let results: Vec<u64> = pool
+            .map_ordered(vec![40, 30, 20, 10, 0], |delay| {
+                thread::sleep(Duration::from_millis(delay));
+                delay
+            })
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(vec![40, 30, 20, 10, 0], results);
+    }
+
+    #[test]
+    fn panics_are_returned_in_place() {
+        let pool = ThreadPool::new(2);
+        let results: Vec<Option<u32>> = pool
+            .map_ordered(vec![1, 2, 3, 4], |n| {
+                assert!(n != 2, "job {n} failed");
+                n
+            })
+            .map(Result::ok)
+            .collect();
+        assert_eq!(vec![Some(1), None, Some(3), Some(4)], results);
+    }
+}
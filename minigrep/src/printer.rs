@@ -0,0 +1,353 @@
+use std::io::{self, BufRead, Write};
+
+use crate::config::Config;
+use crate::json::Object;
+use crate::matcher::Matcher;
+use crate::records::search_records;
+use crate::replace::Replacement;
+use crate::{Match, search_reader};
+
+/// ANSI escape codes for the parts of a line of output.
+const PATH_COLOR: &str = "\x1b[35m";
+const LINE_NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+/// Formats the results of searching one input the way the command line
+/// asked for.
+pub struct Printer<'c> {
+    config: &'c Config,
+    matcher: &'c Matcher,
+    replacement: Option<Replacement>,
+    color: bool,
+    /// Whether any group of lines has been printed yet, in any input, so we
+    /// know when the next group needs a `--` separator.
+    printed_group: bool,
+}
+
+/// Totals over every input searched, for the `--json` summary record.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub inputs: u64,
+    pub inputs_with_matches: u64,
+    pub matched_lines: u64,
+}
+
+impl Summary {
+    pub fn add(&mut self, matched_lines: u64) {
+        self.inputs += 1;
+        if matched_lines > 0 {
+            self.inputs_with_matches += 1;
+        }
+        self.matched_lines += matched_lines;
+    }
+}
+
+impl<'c> Printer<'c> {
+    pub fn new(config: &'c Config, matcher: &'c Matcher) -> Printer<'c> {
+        Printer {
+            config,
+            matcher,
+            replacement: config.replace.as_deref().map(Replacement::parse),
+            color: config.color.enabled(),
+            printed_group: false,
+        }
+    }
+
+    pub fn config(&self) -> &'c Config {
+        self.config
+    }
+
+    pub fn matcher(&self) -> &'c Matcher {
+        self.matcher
+    }
+
+    pub fn replacement(&self) -> Option<&Replacement> {
+        self.replacement.as_ref()
+    }
+
+    /// Searches `reader` and writes the results to `out`, returning how many
+    /// lines were selected. Lines are prefixed with `path` when the run
+    /// covers more than one input.
+    ///
+    /// When context is requested, every group of lines after the first one
+    /// this printer wrote is preceded by a `--` line.
+    pub fn print_matches(
+        &mut self,
+        out: &mut impl Write,
+        path: &str,
+        reader: impl BufRead,
+    ) -> io::Result<u64> {
+        let config = self.config;
+        let options = config.search_options();
+        match config.record_format(path) {
+            Ok(Some(format)) => {
+                let selector = config.selector.as_ref();
+                let results = search_records(self.matcher, reader, format, selector, &options);
+                self.print_results(out, path, results)
+            }
+            Ok(None) => {
+                let results = search_reader(self.matcher, reader, &options);
+                self.print_results(out, path, results)
+            }
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+        }
+    }
+
+    fn print_results<'a>(
+        &mut self,
+        out: &mut impl Write,
+        path: &str,
+        results: impl Iterator<Item = io::Result<Match<'a>>>,
+    ) -> io::Result<u64> {
+        let config = self.config;
+        if config.json {
+            return print_json(out, path, self.matcher, results);
+        }
+        let path = config.show_path().then_some(path);
+
+        let mut count = 0;
+        if config.count {
+            for m in results {
+                if !m?.is_context {
+                    count += 1;
+                }
+            }
+            if let Some(path) = path {
+                self.paint(out, PATH_COLOR, path)?;
+                self.paint(out, SEPARATOR_COLOR, ":")?;
+            }
+            writeln!(out, "{count}")?;
+            return Ok(count);
+        }
+
+        let mut previous = None;
+        for m in results {
+            let m = m?;
+            if previous.is_none_or(|previous| m.line_number > previous + 1) {
+                self.start_group(out)?;
+            }
+            previous = Some(m.line_number);
+            if !m.is_context {
+                count += 1;
+            }
+
+            let separator = if m.is_context { "-" } else { ":" };
+            if let Some(path) = path {
+                self.paint(out, PATH_COLOR, path)?;
+                self.paint(out, SEPARATOR_COLOR, separator)?;
+            }
+            if config.line_number {
+                self.paint(out, LINE_NUMBER_COLOR, &m.line_number.to_string())?;
+                self.paint(out, SEPARATOR_COLOR, separator)?;
+            }
+            if config.fuzzy.is_some() {
+                self.print_edits(out, &m, separator)?;
+            }
+            self.print_line(out, &m)?;
+        }
+        Ok(count)
+    }
+
+    /// Writes a `--` separator if context is on and an earlier group was
+    /// printed. Callers that stitch together output rendered by other
+    /// printers call this before each non-empty piece.
+    pub fn start_group(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let config = self.config;
+        if config.has_context() && !config.count && !config.json && self.printed_group {
+            self.paint(out, SEPARATOR_COLOR, "--")?;
+            writeln!(out)?;
+        }
+        self.printed_group = true;
+        Ok(())
+    }
+
+    /// Writes `~N`, where N is the fewest edits any match on the line
+    /// needed, or a blank column for context lines.
+    fn print_edits(&self, out: &mut impl Write, m: &Match, separator: &str) -> io::Result<()> {
+        let edits = m
+            .matches
+            .iter()
+            .filter_map(|found| self.matcher.edits(&m.line, found))
+            .min();
+        match edits {
+            Some(edits) if !m.is_context => {
+                self.paint(out, LINE_NUMBER_COLOR, &format!("~{edits}"))?
+            }
+            _ => write!(out, "  ")?,
+        }
+        self.paint(out, SEPARATOR_COLOR, separator)
+    }
+
+    /// Writes the line with its matches highlighted, or with them replaced
+    /// and the replacements highlighted when `--replace` is on.
+    fn print_line(&self, out: &mut impl Write, m: &Match) -> io::Result<()> {
+        let (line, highlights) = match &self.replacement {
+            Some(replacement) if !m.matches.is_empty() => {
+                let (line, inserted) = replacement.replace_all(self.matcher, &m.line);
+                (line.into(), inserted)
+            }
+            _ => (m.line.clone(), m.matches.clone()),
+        };
+
+        let mut end = 0;
+        for found in highlights {
+            if found.is_empty() {
+                continue;
+            }
+            out.write_all(line[end..found.start].as_bytes())?;
+            self.paint(out, MATCH_COLOR, &line[found.clone()])?;
+            end = found.end;
+        }
+        writeln!(out, "{}", &line[end..])
+    }
+
+    /// Writes `text`, wrapped in `color` when colors are on.
+    fn paint(&self, out: &mut impl Write, color: &str, text: &str) -> io::Result<()> {
+        if self.color {
+            write!(out, "{color}{text}{RESET}")
+        } else {
+            out.write_all(text.as_bytes())
+        }
+    }
+
+    /// Writes the closing record of a `--json` run; other formats have none.
+    pub fn print_summary(&self, out: &mut impl Write, summary: &Summary) -> io::Result<()> {
+        if !self.config.json {
+            return Ok(());
+        }
+        let record = Object::new()
+            .string("type", "summary")
+            .number("searched", summary.inputs)
+            .number("searched_with_matches", summary.inputs_with_matches)
+            .number("matched_lines", summary.matched_lines)
+            .finish();
+        writeln!(out, "{record}")
+    }
+}
+
+/// Writes one JSON object per line: `begin` before the first result from
+/// an input, a `match` or `context` record for each line and `end` after
+/// the last one. Inputs without results produce nothing.
+///
+/// Offsets are in bytes; `column` is the 1-based byte column of the first
+/// match on the line. Submatches of a fuzzy search also carry `edits`.
+fn print_json<'a>(
+    out: &mut impl Write,
+    path: &str,
+    matcher: &Matcher,
+    results: impl Iterator<Item = io::Result<Match<'a>>>,
+) -> io::Result<u64> {
+    let mut count = 0;
+    let mut begun = false;
+
+    for m in results {
+        let m = m?;
+        if !begun {
+            let record = Object::new()
+                .string("type", "begin")
+                .string("path", path)
+                .finish();
+            writeln!(out, "{record}")?;
+            begun = true;
+        }
+        if !m.is_context {
+            count += 1;
+        }
+
+        let submatches = m
+            .matches
+            .iter()
+            .map(|found| {
+                let submatch = Object::new()
+                    .string("text", &m.line[found.clone()])
+                    .number("start", found.start as u64)
+                    .number("end", found.end as u64);
+                match matcher.edits(&m.line, found) {
+                    Some(edits) => submatch.number("edits", edits as u64),
+                    None => submatch,
+                }
+            })
+            .collect();
+        let column = m.matches.first().map_or(1, |found| found.start + 1);
+        let record = Object::new()
+            .string("type", if m.is_context { "context" } else { "match" })
+            .string("path", path)
+            .number("line_number", m.line_number as u64)
+            .number("column", column as u64)
+            .number("absolute_offset", m.byte_offset as u64)
+            .string("text", &m.line)
+            .array("submatches", submatches)
+            .finish();
+        writeln!(out, "{record}")?;
+    }
+
+    if begun {
+        let record = Object::new()
+            .string("type", "end")
+            .string("path", path)
+            .number("matched_lines", count)
+            .finish();
+        writeln!(out, "{record}")?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ColorChoice;
+
+    #[test]
+    fn highlights_matches() {
+        let config = Config {
+            patterns: vec![String::from("o")],
+            paths: vec![String::from("a.txt"), String::from("b.txt")],
+            line_number: true,
+            color: ColorChoice::Always,
+            ..Config::default()
+        };
+        let matcher = config.matcher().unwrap();
+        let mut out = Vec::new();
+
+        Printer::new(&config, &matcher)
+            .print_matches(&mut out, "a.txt", "frog\n".as_bytes())
+            .unwrap();
+
+        assert_eq!(
+            "\x1b[35ma.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m1\x1b[0m\x1b[36m:\x1b[0m\
+fr\x1b[1;31mo\x1b[0mg\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn json_records() {
+        let config = Config {
+            patterns: vec![String::from("us")],
+            json: true,
+            ..Config::default()
+        };
+        let matcher = config.matcher().unwrap();
+        let mut printer = Printer::new(&config, &matcher);
+        let mut out = Vec::new();
+        let contents = "Who are you?\nThen there's a pair of us - don't tell!\n";
+
+        let count = printer
+            .print_matches(&mut out, "poem.txt", contents.as_bytes())
+            .unwrap();
+
+        assert_eq!(1, count);
+        assert_eq!(
+            "\
+{\"type\":\"begin\",\"path\":\"poem.txt\"}
+{\"type\":\"match\",\"path\":\"poem.txt\",\"line_number\":2,\"column\":24,\"absolute_offset\":13,\
+\"text\":\"Then there's a pair of us - don't tell!\",\
+\"submatches\":[{\"text\":\"us\",\"start\":23,\"end\":25}]}
+{\"type\":\"end\",\"path\":\"poem.txt\",\"matched_lines\":1}
+",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use crate::{ContextWindow, Match, Matcher, SearchOptions};
+
+/// Searches `reader` one line at a time, so memory use is bounded by the
+/// longest line and the amount of context requested rather than by the size
+/// of the input.
+///
+/// Results come out in the same order and shape as `search_context`, but the
+/// lines are owned since the buffer they were read into is reused.
+pub fn search_reader<'m, R: BufRead>(
+    matcher: &'m Matcher,
+    reader: R,
+    options: &SearchOptions,
+) -> SearchReader<'m, R> {
+    SearchReader {
+        matcher,
+        reader,
+        window: ContextWindow::new(options),
+        pending: VecDeque::new(),
+        buffer: String::new(),
+        line_number: 0,
+        byte_offset: 0,
+        done: false,
+    }
+}
+
+pub struct SearchReader<'m, R> {
+    matcher: &'m Matcher,
+    reader: R,
+    window: ContextWindow<'static>,
+    pending: VecDeque<Match<'static>>,
+    buffer: String,
+    line_number: usize,
+    byte_offset: usize,
+    done: bool,
+}
+
+impl<R: BufRead> SearchReader<'_, R> {
+    /// Reads the next line into the context window. Returns `false` at the
+    /// end of the input.
+    fn read_line(&mut self) -> io::Result<bool> {
+        self.buffer.clear();
+        let read = read_line_lossy(&mut self.reader, &mut self.buffer)?;
+        if read == 0 {
+            return Ok(false);
+        }
+
+        let line = self.buffer.strip_suffix('\n').unwrap_or(&self.buffer);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        self.line_number += 1;
+        let byte_offset = self.byte_offset;
+        self.byte_offset += read;
+
+        let matches = self.matcher.find_iter(line);
+        if self.window.wants(&matches) {
+            let candidate = Match {
+                line_number: self.line_number,
+                byte_offset,
+                line: Cow::Owned(line.to_string()),
+                matches,
+                is_context: true,
+            };
+            self.window.push(candidate, &mut self.pending);
+        }
+        Ok(true)
+    }
+}
+
+/// Like `BufRead::read_line`, but invalid UTF-8 is reported as an error
+/// wrapping the `Utf8Error`, so that it can be told apart from other
+/// failures.
+pub(crate) fn read_line(reader: &mut impl BufRead, buf: &mut String) -> io::Result<usize> {
+    let mut bytes = std::mem::take(buf).into_bytes();
+    let start = bytes.len();
+    let result = reader.read_until(b'\n', &mut bytes);
+    match String::from_utf8(bytes) {
+        Ok(text) => {
+            *buf = text;
+            result
+        }
+        Err(e) => {
+            let error = e.utf8_error();
+            let mut bytes = e.into_bytes();
+            bytes.truncate(start);
+            *buf = String::from_utf8(bytes).expect("the start was valid before");
+            Err(io::Error::new(io::ErrorKind::InvalidData, error))
+        }
+    }
+}
+
+/// Like `BufRead::read_line`, but bytes that aren't valid UTF-8 are
+/// replaced with U+FFFD instead of failing, so the rest of the line can
+/// still be searched.
+pub(crate) fn read_line_lossy(reader: &mut impl BufRead, buf: &mut String) -> io::Result<usize> {
+    let mut bytes = Vec::new();
+    let read = reader.read_until(b'\n', &mut bytes)?;
+    match String::from_utf8(bytes) {
+        Ok(text) => buf.push_str(&text),
+        Err(e) => buf.push_str(&String::from_utf8_lossy(e.as_bytes())),
+    }
+    Ok(read)
+}
+
+impl<R: BufRead> Iterator for SearchReader<'_, R> {
+    type Item = io::Result<Match<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            match self.read_line() {
+                Ok(true) => {}
+                Ok(false) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_context;
+
+    #[test]
+    fn matches_search_context() {
+        let matcher = Matcher::literal("us");
+        let contents = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+
+How dreary to be somebody!";
+        let options = SearchOptions {
+            before_context: 1,
+            after_context: 1,
+            invert_match: false,
+        };
+
+        let streamed: Vec<Match> = search_reader(&matcher, contents.as_bytes(), &options)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(search_context(&matcher, contents, &options), streamed);
+    }
+
+    #[test]
+    fn invalid_utf8_is_searched_lossily() {
+        let matcher = Matcher::literal("us");
+        let contents: &[u8] = b"caf\xe9 for us\nnot them\n";
+        let lines: Vec<String> = search_reader(&matcher, contents, &SearchOptions::default())
+            .map(|m| m.map(|m| m.line.into_owned()))
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(vec!["caf\u{fffd} for us"], lines);
+    }
+}
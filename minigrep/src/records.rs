@@ -0,0 +1,671 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+use crate::reader::read_line_lossy;
+use crate::{ContextWindow, Match, Matcher, SearchOptions};
+
+/// Kinds of structured input whose records and fields minigrep understands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Tsv,
+    JsonLines,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Result<Format, String> {
+        match value {
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "jsonl" => Ok(Format::JsonLines),
+            _ => Err(format!(
+                "invalid value '{value}' for '--format' (expected csv, tsv or jsonl)"
+            )),
+        }
+    }
+
+    /// Guesses the format from a file name's extension.
+    pub fn from_path(path: &str) -> Option<Format> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "tsv" | "tab" => Some(Format::Tsv),
+            "jsonl" | "ndjson" => Some(Format::JsonLines),
+            _ => None,
+        }
+    }
+
+    fn delimiter(self) -> u8 {
+        if self == Format::Tsv { b'\t' } else { b',' }
+    }
+}
+
+/// The part of each record a query is restricted to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    /// A CSV or TSV column, by its name in the header or its 1-based number.
+    Column(String),
+    JsonPath(JsonPath),
+}
+
+/// A path to a value inside a JSON document, like `.user.id` or
+/// `.items[0].name`; `.` alone is the whole document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<JsonPath, String> {
+        let invalid = || format!("invalid JSON path '{path}' (expected something like .user.id)");
+        let mut rest = path.strip_prefix('.').ok_or_else(invalid)?;
+        let mut steps = Vec::new();
+
+        while !rest.is_empty() {
+            if let Some(index) = rest.strip_prefix('[') {
+                let (number, after) = index.split_once(']').ok_or_else(invalid)?;
+                steps.push(Step::Index(number.parse().map_err(|_| invalid())?));
+                rest = after.strip_prefix('.').unwrap_or(after);
+            } else {
+                let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                if end == 0 {
+                    return Err(invalid());
+                }
+                steps.push(Step::Key(rest[..end].to_string()));
+                rest = rest[end..].strip_prefix('.').unwrap_or(&rest[end..]);
+            }
+        }
+        Ok(JsonPath { steps })
+    }
+}
+
+/// Searches `reader` a record at a time: a CSV or TSV row, which may span
+/// several lines when a quoted field contains line breaks, or a JSON Lines
+/// document. With a `selector`, only that field of each record is searched
+/// but the whole record is reported.
+///
+/// The first row of CSV and TSV input is taken as the header and is never
+/// reported itself.
+pub fn search_records<'m, R: BufRead>(
+    matcher: &'m Matcher,
+    reader: R,
+    format: Format,
+    selector: Option<&Selector>,
+    options: &SearchOptions,
+) -> SearchRecords<'m, R> {
+    SearchRecords {
+        matcher,
+        reader,
+        format,
+        selector: selector.cloned(),
+        column: None,
+        header_read: false,
+        window: ContextWindow::new(options),
+        pending: VecDeque::new(),
+        line_number: 0,
+        byte_offset: 0,
+        done: false,
+    }
+}
+
+pub struct SearchRecords<'m, R> {
+    matcher: &'m Matcher,
+    reader: R,
+    format: Format,
+    selector: Option<Selector>,
+    /// The selected column, once the header has been read.
+    column: Option<usize>,
+    header_read: bool,
+    window: ContextWindow<'static>,
+    pending: VecDeque<Match<'static>>,
+    line_number: usize,
+    byte_offset: usize,
+    done: bool,
+}
+
+impl<R: BufRead> SearchRecords<'_, R> {
+    /// Reads the next record into the context window. Returns `false` at
+    /// the end of the input.
+    fn read_record(&mut self) -> io::Result<bool> {
+        let mut record = String::new();
+        let first_line = self.line_number + 1;
+        let byte_offset = self.byte_offset;
+        loop {
+            let read = read_line_lossy(&mut self.reader, &mut record)?;
+            if read == 0 {
+                break;
+            }
+            self.line_number += 1;
+            self.byte_offset += read;
+            // An odd number of quotes means a quoted field is still open.
+            // TSV has no quoting, so its records are always one line.
+            let open = self.format == Format::Csv && record.matches('"').count() % 2 == 1;
+            if !open {
+                break;
+            }
+        }
+        if record.is_empty() {
+            return Ok(false);
+        }
+        let record = record.strip_suffix('\n').unwrap_or(&record);
+        let record = record.strip_suffix('\r').unwrap_or(record);
+
+        if self.format != Format::JsonLines && !self.header_read {
+            self.header_read = true;
+            if let Some(Selector::Column(name)) = &self.selector {
+                self.column = Some(self.find_column(name, record)?);
+            }
+            return Ok(true);
+        }
+
+        let field = match (&self.selector, self.column) {
+            (Some(Selector::Column(_)), Some(column)) => csv_field(record, self.format, column),
+            (Some(Selector::JsonPath(path)), _) => json_value(record, &path.steps),
+            _ => None,
+        };
+        let matches = match (&self.selector, field) {
+            (None, _) => self.matcher.find_iter(record),
+            (Some(_), Some(field)) => self
+                .matcher
+                .find_iter(&field.value)
+                .into_iter()
+                .map(|found| field.in_record(found))
+                .collect(),
+            (Some(_), None) => Vec::new(),
+        };
+
+        if self.window.wants(&matches) {
+            let candidate = Match {
+                line_number: first_line,
+                byte_offset,
+                line: Cow::Owned(record.to_string()),
+                matches,
+                is_context: true,
+            };
+            self.window.push(candidate, &mut self.pending);
+        }
+        Ok(true)
+    }
+
+    fn find_column(&self, name: &str, header: &str) -> io::Result<usize> {
+        let mut column = 0;
+        while let Some(field) = csv_field(header, self.format, column) {
+            if field.value == name {
+                return Ok(column);
+            }
+            column += 1;
+        }
+        match name.parse::<usize>() {
+            Ok(number) if (1..=column).contains(&number) => Ok(number - 1),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no column '{name}' in the header"),
+            )),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for SearchRecords<'_, R> {
+    type Item = io::Result<Match<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            match self.read_record() {
+                Ok(true) => {}
+                Ok(false) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// The value of a field, with any quoting or escapes undone, and where each
+/// of its bytes came from in the record so matches can be shown there.
+#[derive(Debug, PartialEq)]
+struct Field {
+    value: String,
+    /// `offsets[i]` is where the text that gave byte `i` of `value` starts
+    /// in the record; the last entry is where the field ends.
+    offsets: Vec<usize>,
+}
+
+impl Field {
+    fn new() -> Field {
+        Field {
+            value: String::new(),
+            offsets: Vec::new(),
+        }
+    }
+
+    /// A field whose text in the record is its value.
+    fn verbatim(record: &str, span: Range<usize>) -> Field {
+        let mut field = Field::new();
+        field.value.push_str(&record[span.clone()]);
+        field.offsets.extend(span.clone());
+        field.offsets.push(span.end);
+        field
+    }
+
+    /// Appends `c`, which was written in the record starting at `from`.
+    fn push(&mut self, c: char, from: usize) {
+        self.value.push(c);
+        self.offsets.extend(std::iter::repeat_n(from, c.len_utf8()));
+    }
+
+    /// Marks the end of the field's text in the record.
+    fn end(mut self, at: usize) -> Field {
+        self.offsets.push(at);
+        self
+    }
+
+    /// Where `found`, a range of the value, was written in the record.
+    fn in_record(&self, found: Range<usize>) -> Range<usize> {
+        self.offsets[found.start]..self.offsets[found.end]
+    }
+}
+
+/// Finds field `column` of a CSV or TSV record. A quoted CSV field's value
+/// is what is inside the quotes, with doubled quotes made single. Quotes
+/// mean nothing special in TSV.
+fn csv_field(record: &str, format: Format, column: usize) -> Option<Field> {
+    let delimiter = format.delimiter();
+    let bytes = record.as_bytes();
+    let mut at = 0;
+    for field in 0.. {
+        let span = if format == Format::Csv && bytes.get(at) == Some(&b'"') {
+            let start = at + 1;
+            let mut end = start;
+            // A doubled quote is an escaped quote, not the end of the field.
+            while end < bytes.len() {
+                if bytes[end] == b'"' {
+                    if bytes.get(end + 1) != Some(&b'"') {
+                        break;
+                    }
+                    end += 1;
+                }
+                end += 1;
+            }
+            at = end + 1;
+            (start..end.min(bytes.len()), true)
+        } else {
+            let end = bytes[at..]
+                .iter()
+                .position(|&b| b == delimiter)
+                .map_or(bytes.len(), |i| at + i);
+            let span = at..end;
+            at = end;
+            (span, false)
+        };
+        if field == column {
+            let (span, quoted) = span;
+            if !quoted {
+                return Some(Field::verbatim(record, span));
+            }
+            let mut value = Field::new();
+            let mut chars = record[span.clone()].char_indices().peekable();
+            while let Some((i, c)) = chars.next() {
+                if c == '"' {
+                    chars.next_if(|&(_, next)| next == '"');
+                }
+                value.push(c, span.start + i);
+            }
+            return Some(value.end(span.end));
+        }
+        // Skip to the start of the next field, past anything stray after a
+        // closing quote.
+        let next = bytes.get(at..)?.iter().position(|&b| b == delimiter)?;
+        at += next + 1;
+    }
+    None
+}
+
+/// Finds the value at `steps` in a JSON document: a string's contents with
+/// its escapes decoded, or the whole text of any other value.
+fn json_value(document: &str, steps: &[Step]) -> Option<Field> {
+    let mut parser = JsonParser {
+        text: document,
+        bytes: document.as_bytes(),
+        at: 0,
+    };
+    let span = parser.find(steps)?;
+    if document[span.clone()].starts_with('"') {
+        parser.at = span.start;
+        parser.string()
+    } else {
+        Some(Field::verbatim(document, span))
+    }
+}
+
+/// Just enough of a JSON parser to walk down a path and skip everything
+/// else.
+struct JsonParser<'a> {
+    text: &'a str,
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl JsonParser<'_> {
+    fn find(&mut self, steps: &[Step]) -> Option<Range<usize>> {
+        self.skip_whitespace();
+        let Some((step, rest)) = steps.split_first() else {
+            let start = self.at;
+            self.skip_value()?;
+            return Some(start..self.at);
+        };
+
+        match (step, self.bytes.get(self.at)?) {
+            (Step::Key(key), b'{') => {
+                self.at += 1;
+                loop {
+                    self.skip_whitespace();
+                    if self.eat(b'}') {
+                        return None;
+                    }
+                    let name = self.string()?.value;
+                    self.skip_whitespace();
+                    if !self.eat(b':') {
+                        return None;
+                    }
+                    if name == *key {
+                        return self.find(rest);
+                    }
+                    self.skip_whitespace();
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    self.eat(b',');
+                }
+            }
+            (Step::Index(index), b'[') => {
+                self.at += 1;
+                for _ in 0..*index {
+                    self.skip_whitespace();
+                    if self.eat(b']') {
+                        return None;
+                    }
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    if !self.eat(b',') {
+                        return None;
+                    }
+                }
+                self.find(rest)
+            }
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.bytes.get(self.at) == Some(&byte);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.at).is_some_and(u8::is_ascii_whitespace) {
+            self.at += 1;
+        }
+    }
+
+    /// Reads a string, decoding escapes, and leaves `at` after it.
+    fn string(&mut self) -> Option<Field> {
+        if !self.eat(b'"') {
+            return None;
+        }
+        let start = self.at;
+        let mut value = Field::new();
+        let mut chars = self.text.get(start..)?.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.at = start + i + 1;
+                    return Some(value.end(start + i));
+                }
+                '\\' => {
+                    let (_, escape) = chars.next()?;
+                    let c = match escape {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let hex = self.text.get(start + i + 2..start + i + 6)?;
+                            let code = u32::from_str_radix(hex, 16).ok()?;
+                            // The four digits are ASCII, one char each.
+                            chars.nth(3);
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        other => other,
+                    };
+                    value.push(c, start + i);
+                }
+                c => value.push(c, start + i),
+            }
+        }
+        None
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        match self.bytes.get(self.at)? {
+            b'"' => {
+                self.string()?;
+            }
+            open @ (b'{' | b'[') => {
+                let close = if *open == b'{' { b'}' } else { b']' };
+                self.at += 1;
+                loop {
+                    self.skip_whitespace();
+                    if self.eat(close) {
+                        break;
+                    }
+                    if *open == b'{' {
+                        self.string()?;
+                        self.skip_whitespace();
+                        if !self.eat(b':') {
+                            return None;
+                        }
+                        self.skip_whitespace();
+                    }
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    if !self.eat(b',') && self.bytes.get(self.at) != Some(&close) {
+                        return None;
+                    }
+                }
+            }
+            _ => {
+                let len = self.bytes[self.at..]
+                    .iter()
+                    .position(|b| matches!(b, b',' | b'}' | b']') || b.is_ascii_whitespace())
+                    .unwrap_or(self.bytes.len() - self.at);
+                if len == 0 {
+                    return None;
+                }
+                self.at += len;
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(results: SearchRecords<&[u8]>) -> Vec<(usize, String)> {
+        results
+            .map(|m| m.map(|m| (m.line_number, m.line.into_owned())))
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn csv_columns_and_multiline_records() {
+        let input = "id,status,note\n\
+                     1,ok,\"all good\"\n\
+                     2,failed,\"said \"\"ok\"\"\n\
+                     then left\"\n\
+                     3,ok,fine\n";
+        let matcher = Matcher::literal("ok");
+        let selector = Selector::Column(String::from("status"));
+        let results = search_records(
+            &matcher,
+            input.as_bytes(),
+            Format::Csv,
+            Some(&selector),
+            &SearchOptions::default(),
+        );
+        assert_eq!(
+            vec![
+                (2, String::from("1,ok,\"all good\"")),
+                (5, String::from("3,ok,fine"))
+            ],
+            selected(results)
+        );
+
+        let selector = Selector::Column(String::from("3"));
+        let results = search_records(
+            &matcher,
+            input.as_bytes(),
+            Format::Csv,
+            Some(&selector),
+            &SearchOptions::default(),
+        );
+        assert_eq!(
+            vec![(3, String::from("2,failed,\"said \"\"ok\"\"\nthen left\""))],
+            selected(results)
+        );
+    }
+
+    #[test]
+    fn json_paths() {
+        let input = r#"{"user": {"id": 42, "name": "bob"}, "tags": ["x", "bob"]}
+{"user": {"id": 7, "name": "alice \"bob\""}, "tags": []}
+"#;
+        let matcher = Matcher::literal("bob");
+        let path = Selector::JsonPath(JsonPath::parse(".user.name").unwrap());
+        let results = search_records(
+            &matcher,
+            input.as_bytes(),
+            Format::JsonLines,
+            Some(&path),
+            &SearchOptions::default(),
+        );
+        let lines: Vec<usize> = selected(results).into_iter().map(|(n, _)| n).collect();
+        assert_eq!(vec![1, 2], lines);
+
+        let first = input.lines().next().unwrap();
+        let tag = JsonPath::parse(".tags[1]").unwrap();
+        let field = json_value(first, &tag.steps).unwrap();
+        assert_eq!("bob", field.value);
+        assert_eq!(51..54, field.in_record(0..3));
+        let id = JsonPath::parse(".user.id").unwrap();
+        assert_eq!(
+            Some(String::from("42")),
+            json_value(first, &id.steps).map(|field| field.value)
+        );
+        assert!(JsonPath::parse("user").is_err());
+    }
+
+    #[test]
+    fn tsv_quotes_are_literal() {
+        let input = "name\tquote\n\
+                     a\t\"half\n\
+                     b\t\"ok\"\n";
+        let matcher = Matcher::literal("ok");
+        let selector = Selector::Column(String::from("quote"));
+        let results = search_records(
+            &matcher,
+            input.as_bytes(),
+            Format::Tsv,
+            Some(&selector),
+            &SearchOptions::default(),
+        );
+        assert_eq!(vec![(3, String::from("b\t\"ok\""))], selected(results));
+        assert_eq!(
+            Some(String::from("\"ok\"")),
+            csv_field("b\t\"ok\"", Format::Tsv, 1).map(|field| field.value)
+        );
+    }
+
+    #[test]
+    fn json_strings() {
+        let document = r#"{"k\u00e9y": "caf\u00e9 \"crème\"", "n": 1}"#;
+        let mut parser = JsonParser {
+            text: document,
+            bytes: document.as_bytes(),
+            at: 1,
+        };
+        assert_eq!(
+            Some(String::from("kéy")),
+            parser.string().map(|field| field.value)
+        );
+        let key = JsonPath::parse(".kéy").unwrap();
+        let field = json_value(document, &key.steps).unwrap();
+        assert_eq!("café \"crème\"", field.value);
+        // Matches of the value are shown over the escapes that wrote it.
+        let found = field.value.find("é \"").unwrap();
+        assert_eq!(
+            r#"\u00e9 \""#,
+            &document[field.in_record(found..found + "é \"".len())]
+        );
+
+        // Long strings are read in one pass.
+        let long = format!(r#"{{"text": "{}", "n": 1}}"#, "é".repeat(200_000));
+        let n = JsonPath::parse(".n").unwrap();
+        assert_eq!(
+            Some(String::from("1")),
+            json_value(&long, &n.steps).map(|field| field.value)
+        );
+    }
+
+    #[test]
+    fn selected_fields_are_searched_unescaped() {
+        let search = |input: &str, format, selector: Selector, query| {
+            let matcher = Matcher::literal(query);
+            let results = search_records(
+                &matcher,
+                input.as_bytes(),
+                format,
+                Some(&selector),
+                &SearchOptions::default(),
+            );
+            results
+                .map(|m| m.map(|m| m.line[m.matches[0].clone()].to_string()))
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap()
+        };
+        let path = || Selector::JsonPath(JsonPath::parse(".s").unwrap());
+
+        let input = "{\"s\": \"caf\\u00e9\"}\n{\"s\": \"a\\\"b\"}\n";
+        assert_eq!(
+            vec!["caf\\u00e9"],
+            search(input, Format::JsonLines, path(), "café")
+        );
+        assert_eq!(
+            vec!["a\\\"b"],
+            search(input, Format::JsonLines, path(), "a\"b")
+        );
+
+        let input = "quote\n\"say \"\"hi\"\"\"\n";
+        let column = Selector::Column(String::from("quote"));
+        assert_eq!(
+            vec!["say \"\"hi\"\""],
+            search(input, Format::Csv, column, "say \"hi\"")
+        );
+    }
+}
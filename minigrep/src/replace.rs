@@ -0,0 +1,256 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::matcher::Matcher;
+use crate::reader::read_line;
+
+/// The text that `--replace` puts in place of each match.
+///
+/// `$0` stands for the whole match and `$1`, `$2`, ... (or `${1}` when a
+/// digit follows) for the capturing groups of a regex; `$$` is a literal
+/// dollar sign. Groups that did not take part in the match expand to
+/// nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Literal(String),
+    Group(usize),
+}
+
+impl Replacement {
+    pub fn parse(template: &str) -> Replacement {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+
+        while let Some(at) = rest.find('$') {
+            literal.push_str(&rest[..at]);
+            rest = &rest[at + 1..];
+
+            let (group, len) = if let Some(braced) = rest.strip_prefix('{') {
+                match braced.find('}') {
+                    Some(end) => (braced[..end].parse().ok(), end + 2),
+                    None => (None, 0),
+                }
+            } else {
+                let digits = rest.chars().take_while(char::is_ascii_digit).count();
+                (rest[..digits].parse().ok(), digits)
+            };
+
+            match group {
+                Some(group) => {
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::Group(group));
+                    rest = &rest[len..];
+                }
+                None => {
+                    literal.push('$');
+                    if let Some(after) = rest.strip_prefix('$') {
+                        rest = after;
+                    }
+                }
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        Replacement { pieces }
+    }
+
+    /// Replaces every match of `matcher` in `line`. Also returns where the
+    /// inserted text ended up in the new line, for highlighting.
+    pub fn replace_all(&self, matcher: &Matcher, line: &str) -> (String, Vec<Range<usize>>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut inserted = Vec::new();
+        let mut end = 0;
+
+        for groups in matcher.captures_iter(line) {
+            let Some(Some(whole)) = groups.first() else {
+                continue;
+            };
+            replaced.push_str(&line[end..whole.start]);
+            let start = replaced.len();
+            for piece in &self.pieces {
+                match piece {
+                    Piece::Literal(text) => replaced.push_str(text),
+                    Piece::Group(index) => {
+                        if let Some(Some(span)) = groups.get(*index) {
+                            replaced.push_str(&line[span.clone()]);
+                        }
+                    }
+                }
+            }
+            inserted.push(start..replaced.len());
+            end = whole.end;
+        }
+        replaced.push_str(&line[end..]);
+
+        (replaced, inserted)
+    }
+}
+
+/// Applies `replacement` to every line of the file at `path`, returning how
+/// many lines changed.
+///
+/// The new contents are written to a temporary file next to the original,
+/// which then replaces it with a rename, so readers see either the old file
+/// or the new one and never a half-written mix. The file is left alone when
+/// nothing changes. With `dry_run`, nothing is written to disk and a unified
+/// diff of the changes is written to `out` instead.
+pub fn rewrite_file(
+    path: &Path,
+    matcher: &Matcher,
+    replacement: &Replacement,
+    dry_run: bool,
+    out: &mut impl Write,
+) -> io::Result<u64> {
+    let reader = BufReader::new(File::open(path)?);
+    let temp_path = temp_path_for(path);
+    let mut temp = if dry_run {
+        None
+    } else {
+        Some(BufWriter::new(File::create(&temp_path)?))
+    };
+
+    let changed = copy_replacing(path, reader, matcher, replacement, &mut temp, out);
+    let Some(temp) = temp else {
+        return changed;
+    };
+    let result = match changed {
+        Ok(changed) if changed > 0 => replace_with(path, temp, &temp_path).map(|()| changed),
+        unchanged => {
+            drop(temp);
+            unchanged
+        }
+    };
+    if !matches!(result, Ok(changed) if changed > 0) {
+        // Best effort: the temporary file may already be gone.
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Moves the finished temporary file over the original, keeping the
+/// original's permissions.
+fn replace_with(path: &Path, temp: BufWriter<File>, temp_path: &Path) -> io::Result<()> {
+    let file = temp.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    fs::set_permissions(temp_path, fs::metadata(path)?.permissions())?;
+    fs::rename(temp_path, path)
+}
+
+fn copy_replacing(
+    path: &Path,
+    mut reader: impl BufRead,
+    matcher: &Matcher,
+    replacement: &Replacement,
+    temp: &mut Option<BufWriter<File>>,
+    diff: &mut impl Write,
+) -> io::Result<u64> {
+    let mut line = String::new();
+    let mut line_number = 0;
+    let mut changed = 0;
+
+    loop {
+        line.clear();
+        // Strictly UTF-8, since writing back a lossy copy would destroy
+        // whatever bytes were replaced.
+        if read_line(&mut reader, &mut line)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        let content = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[content.len()..];
+        let (replaced, inserted) = replacement.replace_all(matcher, content);
+        if !inserted.is_empty() && replaced != content {
+            if temp.is_none() {
+                if changed == 0 {
+                    writeln!(diff, "--- {}", path.display())?;
+                    writeln!(diff, "+++ {}", path.display())?;
+                }
+                writeln!(diff, "@@ -{line_number} +{line_number} @@")?;
+                writeln!(diff, "-{content}")?;
+                writeln!(diff, "+{replaced}")?;
+            }
+            changed += 1;
+        }
+        if let Some(temp) = temp {
+            temp.write_all(replaced.as_bytes())?;
+            temp.write_all(ending.as_bytes())?;
+        }
+    }
+
+    Ok(changed)
+}
+
+pub(crate) fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.minigrep-{}.tmp", process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_expand_groups() {
+        let matcher = Matcher::regex(r"(\w+)@(\w+)", false).unwrap();
+        let replacement = Replacement::parse("${2}1 at $1 ($$0 = $0)");
+        let (replaced, inserted) = replacement.replace_all(&matcher, "mail bob@home now");
+        assert_eq!("mail home1 at bob ($0 = bob@home) now", replaced);
+        assert_eq!(vec![5..33], inserted);
+    }
+
+    #[test]
+    fn rewrites_files_atomically_or_previews_a_diff() {
+        let dir = std::env::temp_dir().join(format!("minigrep-replace-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.conf");
+        fs::write(&path, "host = old\r\nport = 80\nold_host = old\n").unwrap();
+        let matcher = Matcher::literal("old");
+        let replacement = Replacement::parse("new");
+
+        let mut diff = Vec::new();
+        assert_eq!(
+            2,
+            rewrite_file(&path, &matcher, &replacement, true, &mut diff).unwrap()
+        );
+        assert_eq!(
+            format!(
+                "--- {0}\n+++ {0}\n@@ -1 +1 @@\n-host = old\n+host = new\n\
+                 @@ -3 +3 @@\n-old_host = old\n+new_host = new\n",
+                path.display()
+            ),
+            String::from_utf8(diff).unwrap()
+        );
+        assert!(fs::read_to_string(&path).unwrap().contains("old"));
+
+        let mut out = Vec::new();
+        assert_eq!(
+            2,
+            rewrite_file(&path, &matcher, &replacement, false, &mut out).unwrap()
+        );
+        assert!(out.is_empty());
+        assert_eq!(
+            "host = new\r\nport = 80\nnew_host = new\n",
+            fs::read_to_string(&path).unwrap()
+        );
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
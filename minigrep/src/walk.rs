@@ -0,0 +1,275 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::encoding::Encoding;
+
+/// The names of the files whose rules are honored in every directory.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// How many leading bytes are inspected when deciding if a file is binary.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// Returns every file below `root`, sorted by path, skipping `.git`
+/// directories and anything excluded by a `.gitignore` or `.ignore` file
+/// along the way.
+///
+/// Like `grep -r`, symbolic links to directories are not followed. A
+/// directory that can't be read is passed to `report` with the error, and
+/// the walk goes on without it.
+pub fn walk(root: &Path, report: &mut impl FnMut(&Path, io::Error)) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut ignores = Vec::new();
+    walk_dir(root, &mut ignores, &mut files, report);
+    files
+}
+
+fn walk_dir(
+    dir: &Path,
+    ignores: &mut Vec<IgnoreFile>,
+    files: &mut Vec<PathBuf>,
+    report: &mut impl FnMut(&Path, io::Error),
+) {
+    let mut entries =
+        match fs::read_dir(dir).and_then(|entries| entries.collect::<Result<Vec<_>, _>>()) {
+            Ok(entries) => entries,
+            Err(e) => return report(dir, e),
+        };
+    let depth = ignores.len();
+    for name in IGNORE_FILES {
+        if let Some(ignore) = IgnoreFile::load(dir, name) {
+            ignores.push(ignore);
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => {
+                report(&path, e);
+                continue;
+            }
+        };
+        if file_type.is_symlink() && path.is_dir() {
+            continue;
+        }
+        let is_dir = file_type.is_dir();
+        if is_dir && entry.file_name() == ".git" {
+            continue;
+        }
+        if is_ignored(ignores, &path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            walk_dir(&path, ignores, files, report);
+        } else {
+            files.push(path);
+        }
+    }
+
+    ignores.truncate(depth);
+}
+
+/// Whether the start of the file contains a NUL byte, which text never does.
+pub fn is_binary(path: &Path) -> io::Result<bool> {
+    let mut buffer = Vec::with_capacity(BINARY_SNIFF_LEN);
+    File::open(path)?
+        .take(BINARY_SNIFF_LEN as u64)
+        .read_to_end(&mut buffer)?;
+    // UTF-16 text is full of NULs, but announces itself.
+    Ok(buffer.contains(&0) && Encoding::from_bom(&buffer).is_none())
+}
+
+/// Rules are checked from the deepest ignore file outwards and, within a
+/// file, from the last line upwards, so the most specific rule wins.
+fn is_ignored(ignores: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    for ignore in ignores.iter().rev() {
+        let Ok(relative) = path.strip_prefix(&ignore.base) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        if let Some(rule) = ignore
+            .rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(&relative, is_dir))
+        {
+            return !rule.negated;
+        }
+    }
+    false
+}
+
+struct IgnoreFile {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreFile {
+    fn load(dir: &Path, name: &str) -> Option<IgnoreFile> {
+        let contents = fs::read_to_string(dir.join(name)).ok()?;
+        Some(IgnoreFile {
+            base: dir.to_path_buf(),
+            rules: contents.lines().filter_map(Rule::parse).collect(),
+        })
+    }
+}
+
+struct Rule {
+    glob: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let glob = line.trim_start_matches('/').to_string();
+        if glob.is_empty() {
+            return None;
+        }
+        Some(Rule {
+            glob,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// `relative` is the path from the ignore file's directory, using `/`.
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob_match(&self.glob, relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            glob_match(&self.glob, name)
+        }
+    }
+}
+
+/// Matches gitignore-style globs: `*` and `?` stay within one path
+/// component, `**` spans any number of them and `[...]` is a character set.
+fn glob_match(glob: &str, path: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    glob_match_at(&glob, &path)
+}
+
+fn glob_match_at(glob: &[char], path: &[char]) -> bool {
+    match glob.first() {
+        None => path.is_empty(),
+        Some('*') if glob.get(1) == Some(&'*') => {
+            let rest = &glob[2..];
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=path.len()).any(|i| {
+                (i == 0 || path[i - 1] == '/' || rest.is_empty()) && glob_match_at(rest, &path[i..])
+            })
+        }
+        Some('*') => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != '/')
+            .any(|i| glob_match_at(&glob[1..], &path[i..])),
+        Some('?') => {
+            matches!(path.first(), Some(&c) if c != '/') && glob_match_at(&glob[1..], &path[1..])
+        }
+        Some('[') => match (path.first(), glob.iter().position(|&c| c == ']')) {
+            (Some(&c), Some(close)) if close > 1 => {
+                let set = &glob[1..close];
+                let (negated, set) = match set.first() {
+                    Some('!') | Some('^') => (true, &set[1..]),
+                    _ => (false, set),
+                };
+                let mut inside = false;
+                let mut i = 0;
+                while i < set.len() {
+                    if i + 2 < set.len() && set[i + 1] == '-' {
+                        inside |= set[i] <= c && c <= set[i + 2];
+                        i += 3;
+                    } else {
+                        inside |= set[i] == c;
+                        i += 1;
+                    }
+                }
+                c != '/' && inside != negated && glob_match_at(&glob[close + 1..], &path[1..])
+            }
+            _ => path.first() == Some(&'[') && glob_match_at(&glob[1..], &path[1..]),
+        },
+        Some('\\') if glob.len() > 1 => {
+            path.first() == Some(&glob[1]) && glob_match_at(&glob[2..], &path[1..])
+        }
+        Some(&c) => path.first() == Some(&c) && glob_match_at(&glob[1..], &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*.log", "debug.log"));
+        assert!(!glob_match("*.log", "logs/debug.log"));
+        assert!(glob_match("**/build", "a/b/build"));
+        assert!(glob_match("docs/**/*.md", "docs/a/b/readme.md"));
+        assert!(glob_match("file[0-9].txt", "file7.txt"));
+        assert!(!glob_match("file[!0-9].txt", "file7.txt"));
+    }
+
+    #[test]
+    fn rules_respect_anchoring_negation_and_directories() {
+        let rule = Rule::parse("/target/").unwrap();
+        assert!(rule.matches("target", true));
+        assert!(!rule.matches("target", false));
+        assert!(!rule.matches("src/target", true));
+
+        let ignore = IgnoreFile {
+            base: PathBuf::from("repo"),
+            rules: ["*.log", "!keep.log"]
+                .iter()
+                .filter_map(|line| Rule::parse(line))
+                .collect(),
+        };
+        let ignores = [ignore];
+        assert!(is_ignored(&ignores, Path::new("repo/a/debug.log"), false));
+        assert!(!is_ignored(&ignores, Path::new("repo/a/keep.log"), false));
+        assert!(!is_ignored(&ignores, Path::new("repo/a/main.rs"), false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_linked_directories_and_reports_unreadable_ones() {
+        let root = std::env::temp_dir().join(format!("minigrep-walk-{}", std::process::id()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::os::unix::fs::symlink(root.join("src"), root.join("linked")).unwrap();
+        std::os::unix::fs::symlink(root.join("src/main.rs"), root.join("main.rs")).unwrap();
+
+        let mut errors = Vec::new();
+        let files = walk(&root, &mut |path, _| errors.push(path.to_path_buf()));
+        assert_eq!(vec![root.join("main.rs"), root.join("src/main.rs")], files);
+        assert!(errors.is_empty());
+
+        let missing = root.join("missing");
+        assert!(walk(&missing, &mut |path, _| errors.push(path.to_path_buf())).is_empty());
+        assert_eq!(vec![missing], errors);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
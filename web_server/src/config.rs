@@ -0,0 +1,360 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: web_server [OPTIONS] [DIR]
+
+Serves the files in DIR, or the demo pages if no directory is given.
+
+Options:
+      --config FILE         read settings from FILE
+      --address ADDR        IP address to listen on [default: 127.0.0.1]
+      --port PORT           port to listen on [default: 7878]
+      --workers N           number of worker threads [default: 4]
+      --root DIR            directory to serve files from (same as DIR)
+      --idle-timeout T      close connections idle this long [default: 5s]
+      --drain-timeout T     time given to requests at shutdown [default: 30s]
+      --max-requests N      requests answered per connection [default: 100]
+      --max-body-size SIZE  largest request body accepted [default: 1M]
+  -h, --help                print this help
+
+Every option can also be set in the config file as `key = value`, with
+underscores for dashes, or in an environment variable named WEB_SERVER_
+followed by the key in capitals, as in WEB_SERVER_PORT=8080. Options on
+the command line win over environment variables, which win over the
+config file, whose name can be given in WEB_SERVER_CONFIG.
+
+Times are in seconds, or in milliseconds with an 'ms' suffix. Sizes are
+in bytes, or in KiB or MiB with a 'k' or 'm' suffix.
+";
+
+/// The prefix of the environment variables settings are read from.
+const ENV_PREFIX: &str = "WEB_SERVER_";
+
+/// The settings keys, as written in config files.
+const KEYS: [&str; 8] = [
+    "address",
+    "port",
+    "workers",
+    "root",
+    "idle_timeout",
+    "drain_timeout",
+    "max_requests",
+    "max_body_size",
+];
+
+/// How the server is set up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub address: IpAddr,
+    pub port: u16,
+    pub workers: usize,
+    /// The directory to serve files from, if the server serves files.
+    pub root: Option<PathBuf>,
+    pub idle_timeout: Duration,
+    pub drain_timeout: Duration,
+    pub max_requests: usize,
+    pub max_body_size: usize,
+}
+
+/// A setting that could not be used; the message names where it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ConfigError {}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            address: IpAddr::from([127, 0, 0, 1]),
+            port: 7878,
+            workers: 4,
+            root: None,
+            idle_timeout: Duration::from_secs(5),
+            drain_timeout: Duration::from_secs(30),
+            max_requests: 100,
+            max_body_size: 1024 * 1024,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Builds the configuration from the command line, whose first item is
+    /// the program name, the environment, and the config file either of
+    /// them names.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<ServerConfig, ConfigError> {
+        ServerConfig::build_from(args, |name| env::var(name).ok())
+    }
+
+    fn build_from(
+        args: impl Iterator<Item = String>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<ServerConfig, ConfigError> {
+        let flags = parse_args(args)?;
+        let mut config = ServerConfig::default();
+
+        let file = match flags.iter().find(|(key, _)| key == "config") {
+            Some((_, path)) => Some(path.clone()),
+            None => var(&format!("{ENV_PREFIX}CONFIG")),
+        };
+        if let Some(path) = file {
+            let text = fs::read_to_string(&path)
+                .map_err(|e| ConfigError(format!("cannot read config file '{path}': {e}")))?;
+            for (line, key, value) in parse_file(&text)
+                .map_err(|(line, message)| ConfigError(format!("{path}:{line}: {message}")))?
+            {
+                config
+                    .set(key, &value)
+                    .map_err(|message| ConfigError(format!("{path}:{line}: {message}")))?;
+            }
+        }
+
+        for key in KEYS {
+            let name = format!("{ENV_PREFIX}{}", key.to_ascii_uppercase());
+            if let Some(value) = var(&name) {
+                config
+                    .set(key, &value)
+                    .map_err(|message| ConfigError(format!("{name}: {message}")))?;
+            }
+        }
+
+        for (key, value) in &flags {
+            if key != "config" {
+                config.set(key, value).map_err(|message| {
+                    ConfigError(format!("--{}: {message}", key.replace('_', "-")))
+                })?;
+            }
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Sets the setting called `key` from its text.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let invalid = |expected: &str| format!("invalid value '{value}' (expected {expected})");
+        match key {
+            "address" => self.address = value.parse().map_err(|_| invalid("an IP address"))?,
+            "port" => self.port = value.parse().map_err(|_| invalid("a port number"))?,
+            "workers" => self.workers = value.parse().map_err(|_| invalid("a number"))?,
+            "root" => self.root = Some(PathBuf::from(value)),
+            "idle_timeout" => {
+                self.idle_timeout = parse_duration(value).ok_or_else(|| invalid("a time"))?;
+            }
+            "drain_timeout" => {
+                self.drain_timeout = parse_duration(value).ok_or_else(|| invalid("a time"))?;
+            }
+            "max_requests" => self.max_requests = value.parse().map_err(|_| invalid("a number"))?,
+            "max_body_size" => {
+                self.max_body_size = parse_size(value).ok_or_else(|| invalid("a size"))?;
+            }
+            _ => return Err(format!("unknown setting '{key}'")),
+        }
+        Ok(())
+    }
+
+    /// Checks the settings that parse but can't work.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let error = |message: &str| Err(ConfigError(message.to_string()));
+        if self.workers == 0 {
+            return error("workers must be at least 1");
+        }
+        if self.max_requests == 0 {
+            return error("max_requests must be at least 1");
+        }
+        if self.idle_timeout.is_zero() {
+            return error("idle_timeout must be longer than zero");
+        }
+        if let Some(root) = &self.root
+            && !root.is_dir()
+        {
+            return Err(ConfigError(format!(
+                "root '{}' is not a directory",
+                root.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Splits the command line into (key, value) pairs, with keys spelled as
+/// in config files. A bare argument is the document root.
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut args = args.skip(1);
+    let mut flags = Vec::new();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            if arg.starts_with('-') {
+                return Err(ConfigError(format!("unknown option '{arg}'")));
+            }
+            flags.push(("root".to_string(), arg));
+            continue;
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (flag, None),
+        };
+        let key = name.replace('-', "_");
+        if key != "config" && !KEYS.contains(&key.as_str()) {
+            return Err(ConfigError(format!("unknown option '--{name}'")));
+        }
+        let value = match value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(ConfigError(format!("option '--{name}' needs a value"))),
+        };
+        flags.push((key, value));
+    }
+    Ok(flags)
+}
+
+/// A setting from a config file: its line number, key and value.
+type Setting<'a> = (usize, &'a str, String);
+
+/// Reads `key = value` lines, skipping blank lines and `#` comments. Values
+/// may be quoted. Returns each setting with its line number, or the line
+/// number and problem of the first line that can't be read.
+fn parse_file(text: &str) -> Result<Vec<Setting<'_>>, (usize, String)> {
+    let mut settings = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err((line_number, "expected 'key = value'".to_string()));
+        };
+        let key = key.trim();
+        if !KEYS.contains(&key) {
+            return Err((line_number, format!("unknown setting '{key}'")));
+        }
+        let value = value.trim();
+        let value = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, rest)) if rest.trim().is_empty() || rest.trim().starts_with('#') => {
+                    value.to_string()
+                }
+                _ => return Err((line_number, "unterminated string".to_string())),
+            },
+            // Without quotes, a comment can follow the value.
+            None => value.split('#').next().unwrap_or("").trim().to_string(),
+        };
+        settings.push((line_number, key, value));
+    }
+    Ok(settings)
+}
+
+/// Reads a time in seconds, or in milliseconds with an `ms` suffix. An `s`
+/// suffix is allowed too.
+fn parse_duration(text: &str) -> Option<Duration> {
+    if let Some(millis) = text.strip_suffix("ms") {
+        return millis.parse().ok().map(Duration::from_millis);
+    }
+    let secs = text.strip_suffix('s').unwrap_or(text);
+    secs.parse().ok().map(Duration::from_secs)
+}
+
+/// Reads a size in bytes, or in KiB or MiB with a `k` or `m` suffix.
+fn parse_size(text: &str) -> Option<usize> {
+    let (digits, unit) = match text.char_indices().last()? {
+        (i, 'k' | 'K') => (&text[..i], 1024),
+        (i, 'm' | 'M') => (&text[..i], 1024 * 1024),
+        _ => (text, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn build(args: &[&str], vars: &[(&str, &str)]) -> Result<ServerConfig, String> {
+        let args = ["web_server"].iter().chain(args).map(|arg| arg.to_string());
+        ServerConfig::build_from(args, |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn flags_override_environment_override_file() {
+        let path = std::env::temp_dir().join(format!("web-server-config-{}.toml", process::id()));
+        fs::write(
+            &path,
+            "# deployment settings\n\
+             address = \"0.0.0.0\"\n\
+             port = 80 # overridden\n\
+             workers = 2\n\
+             max_body_size = \"16k\"\n",
+        )
+        .unwrap();
+
+        let config = build(
+            &["--port=8080", "--idle-timeout", "250ms"],
+            &[
+                ("WEB_SERVER_CONFIG", path.to_str().unwrap()),
+                ("WEB_SERVER_PORT", "9000"),
+                ("WEB_SERVER_WORKERS", "8"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(IpAddr::from([0, 0, 0, 0]), config.address);
+        assert_eq!(8080, config.port);
+        assert_eq!(8, config.workers);
+        assert_eq!(16 * 1024, config.max_body_size);
+        assert_eq!(Duration::from_millis(250), config.idle_timeout);
+        assert_eq!(Duration::from_secs(30), config.drain_timeout);
+
+        fs::write(&path, "port = 80\nthreads = 3\n").unwrap();
+        let error = build(&["--config", path.to_str().unwrap()], &[]).unwrap_err();
+        assert!(error.ends_with(":2: unknown setting 'threads'"), "{error}");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_settings() {
+        for (args, vars, error) in [
+            (
+                &["--port", "70000"][..],
+                &[][..],
+                "--port: invalid value '70000'",
+            ),
+            (&["--workers=0"], &[], "workers must be at least 1"),
+            (&["--verbose"], &[], "unknown option '--verbose'"),
+            (&["--port"], &[], "option '--port' needs a value"),
+            (
+                &[],
+                &[("WEB_SERVER_ADDRESS", "localhost")],
+                "WEB_SERVER_ADDRESS: invalid value",
+            ),
+            (
+                &["/no/such/dir"],
+                &[],
+                "root '/no/such/dir' is not a directory",
+            ),
+            (
+                &["--config", "/no/such.toml"],
+                &[],
+                "cannot read config file",
+            ),
+        ] {
+            let message = build(args, vars).unwrap_err();
+            assert!(message.starts_with(error), "{args:?}: {message}");
+        }
+    }
+}
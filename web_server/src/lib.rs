@@ -1,3 +1,19 @@
+pub mod config;
+pub mod headers;
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod server;
+pub mod static_files;
+
+pub use config::{ConfigError, ServerConfig};
+pub use headers::Headers;
+pub use request::{ParseError, Request};
+pub use response::{Body, Response, Status};
+pub use router::{Handler, Params, RouteError, Router};
+pub use server::{Server, Shutdown};
+pub use static_files::{Served, StaticFiles};
+
 use std::{
     sync::{Arc, Mutex, mpsc},
     thread,
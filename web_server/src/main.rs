@@ -1,45 +1,76 @@
-use std::{
-    fs,
-    io::{BufReader, prelude::*},
-    net::{TcpListener, TcpStream},
-    thread,
-    time::Duration,
+use std::{env, process, thread, time::Duration};
+use web_server::{
+    Params, Request, Response, Router, Server, ServerConfig, StaticFiles, Status, config,
 };
-use web_server::ThreadPool;
 
 fn main() {
-    let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
-    let pool = ThreadPool::new(4);
+    if env::args()
+        .skip(1)
+        .any(|arg| arg == "-h" || arg == "--help")
+    {
+        print!("{}", config::USAGE);
+        return;
+    }
+    let config = ServerConfig::build(env::args()).unwrap_or_else(|e| {
+        eprintln!("web_server: {e}");
+        eprintln!("Try 'web_server --help' for more information.");
+        process::exit(2);
+    });
 
-    for stream in listener.incoming().take(2) {
-        let stream = stream.unwrap();
+    // Given a directory, serve the files in it instead of the demo pages.
+    let router = match &config.root {
+        Some(root) => static_router(StaticFiles::new(root)),
+        None => router(),
+    };
+    let server = Server::from_config(&config, router).unwrap_or_else(|e| {
+        eprintln!(
+            "web_server: cannot listen on {}:{}: {e}",
+            config.address, config.port
+        );
+        process::exit(1);
+    });
 
-        pool.execute(|| {
-            handle_connection(stream);
-        });
+    if let Err(e) = server.stop_on_signals().run() {
+        eprintln!("web_server: {e}");
+        process::exit(1);
     }
 
     println!("Shutting down.");
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&stream);
-    let request_line = buf_reader.lines().next().unwrap().unwrap();
-
-    let (status_line, filename) = match &request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html"),
-        "GET /sleep HTTP/1.1" => {
-            thread::sleep(Duration::from_secs(5));
-            ("HTTP/1.1 200 OK", "hello.html")
-        }
-        _ => ("HTTP/1.1 404 NOT FOUND", "404.html"),
-    };
+// The demo pages are built in, so the server works from any directory.
+const HELLO_PAGE: &str = include_str!("../hello.html");
+const NOT_FOUND_PAGE: &str = include_str!("../404.html");
+
+fn not_found(_request: &Request, _params: &Params) -> Response {
+    Response::new(Status::NotFound).html(NOT_FOUND_PAGE)
+}
 
-    let contents = fs::read_to_string(filename).unwrap();
-    let length = contents.len();
+fn router() -> Router {
+    let mut router: Router = Router::new();
+    router
+        .get("/", Box::new(hello))
+        .get("/sleep", Box::new(sleep))
+        .fallback(Box::new(not_found));
+    router
+}
 
-    let response = format!("{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}");
+fn hello(_request: &Request, _params: &Params) -> Response {
+    Response::new(Status::Ok).html(HELLO_PAGE)
+}
 
-    stream.write_all(response.as_bytes()).unwrap();
+fn sleep(request: &Request, params: &Params) -> Response {
+    thread::sleep(Duration::from_secs(5));
+    hello(request, params)
 }
 
+fn static_router(files: StaticFiles) -> Router {
+    let mut router: Router = Router::new();
+    router.get(
+        "/*path",
+        Box::new(move |request: &Request, params: &Params| {
+            files.respond(params.get("path").unwrap_or(""), request)
+        }),
+    );
+    router
+}
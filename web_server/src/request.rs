@@ -0,0 +1,346 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+use crate::headers::Headers;
+
+/// The longest request line or header line accepted, in bytes.
+const MAX_LINE_LEN: u64 = 8 * 1024;
+
+/// The most header fields a request may have.
+const MAX_HEADERS: usize = 100;
+
+/// An HTTP/1.x request, read in full from a connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// The path of the request target, without the query string.
+    pub path: String,
+    /// Whatever followed the `?` in the request target, if anything did.
+    pub query: Option<String>,
+    /// `HTTP/1.1` or `HTTP/1.0`.
+    pub version: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+/// Why a request could not be read.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The connection closed before a request started, which is how a
+    /// client normally ends a connection.
+    Closed,
+    Io(io::Error),
+    /// The request was malformed; the reason says how.
+    BadRequest(&'static str),
+    /// The body is larger than the limit it was read with.
+    BodyTooLarge,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Closed => f.write_str("connection closed"),
+            ParseError::Io(e) => write!(f, "{e}"),
+            ParseError::BadRequest(reason) => write!(f, "bad request: {reason}"),
+            ParseError::BodyTooLarge => f.write_str("body too large"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+impl Request {
+    /// Reads one request: the request line, the header fields and a body
+    /// framed by `Content-Length` or chunked transfer encoding, of at most
+    /// `max_body_size` bytes.
+    pub fn read_from(
+        reader: &mut impl BufRead,
+        max_body_size: usize,
+    ) -> Result<Request, ParseError> {
+        let line = match read_line(reader)? {
+            Some(line) => line,
+            None => return Err(ParseError::Closed),
+        };
+
+        let mut parts = line.split(' ');
+        let (Some(method), Some(target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(ParseError::BadRequest("malformed request line"));
+        };
+        if method.is_empty() || !method.bytes().all(is_token_byte) {
+            return Err(ParseError::BadRequest("invalid method"));
+        }
+        if !target.starts_with('/') {
+            return Err(ParseError::BadRequest("request target must start with '/'"));
+        }
+        if version != "HTTP/1.1" && version != "HTTP/1.0" {
+            return Err(ParseError::BadRequest("unsupported HTTP version"));
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query.to_string())),
+            None => (target, None),
+        };
+
+        let headers = read_headers(reader)?;
+        let body = read_body(reader, &headers, max_body_size)?;
+
+        Ok(Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query,
+            version: version.to_string(),
+            headers,
+            body,
+        })
+    }
+
+    /// Whether the client wants the connection kept open for another
+    /// request: by default in HTTP/1.1, only when asked to in HTTP/1.0.
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.headers.get_all("Connection").any(|value| {
+                value
+                    .split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case(option))
+            })
+        };
+        if self.version == "HTTP/1.0" {
+            has_option("keep-alive")
+        } else {
+            !has_option("close")
+        }
+    }
+}
+
+/// Reads a line ending in CRLF (or a bare LF) and returns it without the
+/// line ending, or `None` if the input ended first.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(if line.len() as u64 + 1 >= MAX_LINE_LEN {
+            ParseError::BadRequest("line too long")
+        } else {
+            ParseError::BadRequest("unexpected end of request")
+        });
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::BadRequest("line is not valid UTF-8"))
+}
+
+fn read_headers(reader: &mut impl BufRead) -> Result<Headers, ParseError> {
+    let mut headers = Headers::new();
+    let mut count = 0;
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::BadRequest("unexpected end of headers"))?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if line.starts_with([' ', '\t']) {
+            return Err(ParseError::BadRequest(
+                "folded header lines are not supported",
+            ));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ParseError::BadRequest("header line without a colon"))?;
+        if name.is_empty() || !name.bytes().all(is_token_byte) {
+            return Err(ParseError::BadRequest("invalid header name"));
+        }
+        count += 1;
+        if count > MAX_HEADERS {
+            return Err(ParseError::BadRequest("too many header fields"));
+        }
+        headers.append(name, value.trim_matches([' ', '\t']));
+    }
+}
+
+fn read_body(
+    reader: &mut impl BufRead,
+    headers: &Headers,
+    max_size: usize,
+) -> Result<Vec<u8>, ParseError> {
+    if let Some(encoding) = headers.get("Transfer-Encoding") {
+        // Chunked has to come last; anything else leaves no way to tell
+        // where the body ends.
+        let last = encoding.rsplit(',').next().unwrap_or("").trim();
+        if !last.eq_ignore_ascii_case("chunked") {
+            return Err(ParseError::BadRequest("unsupported transfer encoding"));
+        }
+        if headers.get("Content-Length").is_some() {
+            return Err(ParseError::BadRequest(
+                "both Content-Length and Transfer-Encoding given",
+            ));
+        }
+        return read_chunked(reader, max_size);
+    }
+
+    let mut lengths = headers.get_all("Content-Length");
+    let Some(length) = lengths.next() else {
+        return Ok(Vec::new());
+    };
+    if lengths.any(|other| other != length) {
+        return Err(ParseError::BadRequest("conflicting Content-Length fields"));
+    }
+    let length = parse_length(length, 10)?;
+    if length > max_size {
+        return Err(ParseError::BodyTooLarge);
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(end_of_body)?;
+    Ok(body)
+}
+
+/// Reads a body sent as a series of chunks, each preceded by its size in
+/// hexadecimal, up to a chunk of size zero and the (ignored) trailer fields.
+fn read_chunked(reader: &mut impl BufRead, max_size: usize) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::BadRequest("unexpected end of body"))?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = parse_length(size, 16)?;
+        if size == 0 {
+            break;
+        }
+        if size > max_size - body.len() {
+            return Err(ParseError::BodyTooLarge);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).map_err(end_of_body)?;
+        if read_line(reader)?.as_deref() != Some("") {
+            return Err(ParseError::BadRequest("chunk not followed by CRLF"));
+        }
+    }
+    read_headers(reader)?;
+    Ok(body)
+}
+
+fn parse_length(text: &str, radix: u32) -> Result<usize, ParseError> {
+    if text.is_empty() || !text.chars().all(|c| c.is_digit(radix)) {
+        return Err(ParseError::BadRequest("invalid body length"));
+    }
+    usize::from_str_radix(text, radix).map_err(|_| ParseError::BodyTooLarge)
+}
+
+fn end_of_body(e: io::Error) -> ParseError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        ParseError::BadRequest("body shorter than announced")
+    } else {
+        ParseError::Io(e)
+    }
+}
+
+/// Whether `byte` may appear in a method or header name.
+fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Request, ParseError> {
+        Request::read_from(&mut text.as_bytes(), 1024 * 1024)
+    }
+
+    #[test]
+    fn request_line_headers_and_body() {
+        let request = parse(
+            "POST /users?active=1 HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             content-length: 5\r\n\
+             \r\n\
+             hello",
+        )
+        .unwrap();
+        assert_eq!("POST", request.method);
+        assert_eq!("/users", request.path);
+        assert_eq!(Some("active=1"), request.query.as_deref());
+        assert_eq!(Some("5"), request.headers.get("Content-Length"));
+        assert_eq!(b"hello", &request.body[..]);
+    }
+
+    #[test]
+    fn chunked_bodies() {
+        let request = parse(
+            "PUT /notes HTTP/1.1\r\n\
+             Transfer-Encoding: chunked\r\n\
+             \r\n\
+             4;ext=1\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(b"Wikipedia", &request.body[..]);
+    }
+
+    #[test]
+    fn connection_persistence() {
+        assert!(parse("GET / HTTP/1.1\r\n\r\n").unwrap().keep_alive());
+        assert!(
+            !parse("GET / HTTP/1.1\r\nConnection: Close\r\n\r\n")
+                .unwrap()
+                .keep_alive()
+        );
+        assert!(!parse("GET / HTTP/1.0\r\n\r\n").unwrap().keep_alive());
+        assert!(
+            parse("GET / HTTP/1.0\r\nConnection: foo, keep-alive\r\n\r\n")
+                .unwrap()
+                .keep_alive()
+        );
+    }
+
+    #[test]
+    fn pipelined_requests_are_read_one_at_a_time() {
+        let mut input = "GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 2\r\n\r\nhiGET /c HTTP/1.1\r\n\r\n".as_bytes();
+        let paths: Vec<String> = std::iter::from_fn(|| Request::read_from(&mut input, 1024).ok())
+            .map(|request| request.path)
+            .collect();
+        assert_eq!(vec!["/a", "/b", "/c"], paths);
+    }
+
+    #[test]
+    fn malformed_requests() {
+        assert!(matches!(parse(""), Err(ParseError::Closed)));
+        for bad in [
+            "GET /\r\n\r\n",
+            "GET / HTTP/2\r\n\r\n",
+            "GET / HTTP/1.1\r\nNo colon\r\n\r\n",
+            "GET / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort",
+            "GET / HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\n",
+        ] {
+            assert!(
+                matches!(parse(bad), Err(ParseError::BadRequest(_))),
+                "{bad:?} should be rejected"
+            );
+        }
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 99999999\r\n\r\n"),
+            Err(ParseError::BodyTooLarge)
+        ));
+    }
+}
@@ -0,0 +1,449 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+
+use crate::headers::Headers;
+use crate::request::Request;
+
+/// The status codes the server sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Created,
+    NoContent,
+    MovedPermanently,
+    Found,
+    SeeOther,
+    NotModified,
+    BadRequest,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    ContentTooLarge,
+    InternalServerError,
+    ServiceUnavailable,
+}
+
+impl Status {
+    pub fn code(self) -> u16 {
+        match self {
+            Status::Ok => 200,
+            Status::Created => 201,
+            Status::NoContent => 204,
+            Status::MovedPermanently => 301,
+            Status::Found => 302,
+            Status::SeeOther => 303,
+            Status::NotModified => 304,
+            Status::BadRequest => 400,
+            Status::Forbidden => 403,
+            Status::NotFound => 404,
+            Status::MethodNotAllowed => 405,
+            Status::ContentTooLarge => 413,
+            Status::InternalServerError => 500,
+            Status::ServiceUnavailable => 503,
+        }
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Created => "Created",
+            Status::NoContent => "No Content",
+            Status::MovedPermanently => "Moved Permanently",
+            Status::Found => "Found",
+            Status::SeeOther => "See Other",
+            Status::NotModified => "Not Modified",
+            Status::BadRequest => "Bad Request",
+            Status::Forbidden => "Forbidden",
+            Status::NotFound => "Not Found",
+            Status::MethodNotAllowed => "Method Not Allowed",
+            Status::ContentTooLarge => "Content Too Large",
+            Status::InternalServerError => "Internal Server Error",
+            Status::ServiceUnavailable => "Service Unavailable",
+        }
+    }
+
+    /// Whether responses with this status never have a body.
+    fn is_bodiless(self) -> bool {
+        matches!(self, Status::NoContent | Status::NotModified)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+/// The body of a response.
+pub enum Body {
+    Empty,
+    Bytes(Vec<u8>),
+    /// A file, sent from wherever it is positioned to its end.
+    File(File),
+    /// Anything else that can be read. Its length isn't known in advance,
+    /// so it is sent chunked to HTTP/1.1 clients, and by closing the
+    /// connection after it to HTTP/1.0 ones.
+    Stream(Box<dyn Read + Send>),
+}
+
+impl Body {
+    /// The length of the body, if it can be known without reading it.
+    fn len(&self) -> io::Result<Option<u64>> {
+        match self {
+            Body::Empty => Ok(Some(0)),
+            Body::Bytes(bytes) => Ok(Some(bytes.len() as u64)),
+            Body::File(file) => {
+                let len = file.metadata()?.len();
+                let position = (&*file).stream_position()?;
+                Ok(Some(len.saturating_sub(position)))
+            }
+            Body::Stream(_) => Ok(None),
+        }
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Body::Empty => f.write_str("Empty"),
+            Body::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            Body::File(file) => f.debug_tuple("File").field(file).finish(),
+            Body::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Body {
+        Body::Bytes(text.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(text: &str) -> Body {
+        Body::Bytes(text.as_bytes().to_vec())
+    }
+}
+
+impl From<File> for Body {
+    fn from(file: File) -> Body {
+        Body::File(file)
+    }
+}
+
+/// An HTTP response, built up from a status:
+///
+/// ```
+/// use web_server::{Response, Status};
+///
+/// let response = Response::new(Status::Created)
+///     .header("Location", "/users/42")
+///     .text("created\n");
+/// assert_eq!(Some("/users/42"), response.headers().get("Location"));
+/// ```
+///
+/// `Content-Length` and `Transfer-Encoding` are worked out from the body
+/// when the response is written, and should not be set by hand.
+#[derive(Debug)]
+pub struct Response {
+    status: Status,
+    headers: Headers,
+    body: Body,
+}
+
+impl Response {
+    pub fn new(status: Status) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Body::Empty,
+        }
+    }
+
+    /// A response that sends the client to `location` instead.
+    pub fn redirect(status: Status, location: &str) -> Response {
+        Response::new(status).header("Location", location)
+    }
+
+    /// Adds a header field.
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Adds every field in `headers`.
+    pub fn headers_from(mut self, headers: &Headers) -> Response {
+        for (name, value) in headers.iter() {
+            self.headers.append(name, value);
+        }
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Body>) -> Response {
+        self.body = body.into();
+        self
+    }
+
+    /// Sets a plain text body.
+    pub fn text(self, text: impl Into<String>) -> Response {
+        self.header("Content-Type", "text/plain; charset=utf-8")
+            .body(text.into())
+    }
+
+    /// Sets an HTML body.
+    pub fn html(self, html: impl Into<String>) -> Response {
+        self.header("Content-Type", "text/html; charset=utf-8")
+            .body(html.into())
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    /// Writes the response to `request` on `writer`, which the client can
+    /// go on using for more requests if `keep_alive` is set. `request` is
+    /// `None` when the response is to one that could not be read.
+    ///
+    /// Returns whether the connection can in fact be kept open: it can't
+    /// after a body of unknown length sent to an HTTP/1.0 client, since
+    /// closing it is the only way to mark the end, nor after a file that
+    /// turned out shorter than its `Content-Length`.
+    pub fn write_to(
+        self,
+        writer: &mut impl Write,
+        request: Option<&Request>,
+        mut keep_alive: bool,
+    ) -> io::Result<bool> {
+        let http_10 = request.is_some_and(|request| request.version == "HTTP/1.0");
+        let head = request.is_some_and(|request| request.method == "HEAD");
+        let Response {
+            status,
+            mut headers,
+            mut body,
+        } = self;
+        if status.is_bodiless() {
+            body = Body::Empty;
+        }
+        headers.remove("Content-Length");
+        headers.remove("Transfer-Encoding");
+        headers.remove("Connection");
+
+        let len = body.len()?;
+        let chunked = match len {
+            _ if status.is_bodiless() => false,
+            Some(len) => {
+                headers.append("Content-Length", &len.to_string());
+                false
+            }
+            None if http_10 => {
+                keep_alive = false;
+                false
+            }
+            None => {
+                headers.append("Transfer-Encoding", "chunked");
+                true
+            }
+        };
+        if !keep_alive {
+            headers.append("Connection", "close");
+        } else if http_10 {
+            headers.append("Connection", "keep-alive");
+        }
+
+        let mut lines = format!("HTTP/1.1 {status}\r\n");
+        for (name, value) in headers.iter() {
+            lines.push_str(&format!("{name}: {value}\r\n"));
+        }
+        lines.push_str("\r\n");
+        writer.write_all(lines.as_bytes())?;
+
+        if !head {
+            match body {
+                Body::Empty => {}
+                Body::Bytes(bytes) => writer.write_all(&bytes)?,
+                // Only what Content-Length promised is sent, even if the
+                // file has grown since. If it has shrunk, the body comes up
+                // short and the connection must close, or the client would
+                // take the next response for the rest of it.
+                Body::File(file) => {
+                    let len = len.unwrap_or(0);
+                    if io::copy(&mut file.take(len), writer)? < len {
+                        writer.flush()?;
+                        return Ok(false);
+                    }
+                }
+                Body::Stream(mut reader) if chunked => write_chunked(&mut reader, writer)?,
+                Body::Stream(mut reader) => {
+                    io::copy(&mut reader, writer)?;
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(keep_alive)
+    }
+}
+
+fn write_chunked(reader: &mut impl Read, writer: &mut impl Write) -> io::Result<()> {
+    let mut buf = [0; 8 * 1024];
+    loop {
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        write!(writer, "{read:x}\r\n")?;
+        writer.write_all(&buf[..read])?;
+        writer.write_all(b"\r\n")?;
+    }
+    writer.write_all(b"0\r\n\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn written(response: Response, request: &str, keep_alive: bool) -> (String, bool) {
+        let request = Request::read_from(&mut request.as_bytes(), 0).unwrap();
+        let mut out = Vec::new();
+        let kept = response
+            .write_to(&mut out, Some(&request), keep_alive)
+            .unwrap();
+        (String::from_utf8(out).unwrap(), kept)
+    }
+
+    #[test]
+    fn lengths_and_connection_headers() {
+        let response = Response::new(Status::Ok).text("hello");
+        let (text, kept) = written(response, "GET / HTTP/1.1\r\n\r\n", true);
+        assert_eq!(
+            "HTTP/1.1 200 OK\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: 5\r\n\
+             \r\n\
+             hello",
+            text
+        );
+        assert!(kept);
+
+        let response = Response::new(Status::NotModified).header("ETag", "\"1\"");
+        let (text, _) = written(response, "GET / HTTP/1.0\r\n\r\n", false);
+        assert_eq!(
+            "HTTP/1.1 304 Not Modified\r\nETag: \"1\"\r\nConnection: close\r\n\r\n",
+            text
+        );
+
+        let response = Response::new(Status::Ok).body("abc");
+        let (text, _) = written(response, "HEAD / HTTP/1.1\r\n\r\n", true);
+        assert_eq!("HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n", text);
+    }
+
+    #[test]
+    fn files_are_sent_up_to_their_length() {
+        let path = std::env::temp_dir().join(format!("web-server-body-{}", std::process::id()));
+        fs::write(&path, "0123456789").unwrap();
+        let mut file = File::open(&path).unwrap();
+        file.seek(io::SeekFrom::Start(4)).unwrap();
+        let response = Response::new(Status::Ok).body(file);
+
+        // The file grows between its length being taken and it being sent.
+        let mut out = io::Cursor::new(Vec::new());
+        let request = Request::read_from(&mut &b"GET / HTTP/1.1\r\n\r\n"[..], 0).unwrap();
+        let mut growing = ChangesOnHead {
+            out: &mut out,
+            path: &path,
+            grow: true,
+        };
+        response
+            .write_to(&mut growing, Some(&request), true)
+            .unwrap();
+        let text = String::from_utf8(out.into_inner()).unwrap();
+        assert!(text.ends_with("Content-Length: 6\r\n\r\n456789"), "{text}");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn files_that_shrink_close_the_connection() {
+        let path = std::env::temp_dir().join(format!("web-server-short-{}", std::process::id()));
+        fs::write(&path, "0123456789").unwrap();
+        let response = Response::new(Status::Ok).body(File::open(&path).unwrap());
+
+        let mut out = io::Cursor::new(Vec::new());
+        let request = Request::read_from(&mut &b"GET / HTTP/1.1\r\n\r\n"[..], 0).unwrap();
+        let mut shrinking = ChangesOnHead {
+            out: &mut out,
+            path: &path,
+            grow: false,
+        };
+        let kept = response
+            .write_to(&mut shrinking, Some(&request), true)
+            .unwrap();
+        let text = String::from_utf8(out.into_inner()).unwrap();
+        assert!(text.ends_with("Content-Length: 10\r\n\r\n"), "{text}");
+        assert!(!kept);
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// A writer that changes a file once the head has been written:
+    /// appending to it if `grow` is set, or emptying it otherwise.
+    struct ChangesOnHead<'a> {
+        out: &'a mut io::Cursor<Vec<u8>>,
+        path: &'a std::path::Path,
+        grow: bool,
+    }
+
+    impl Write for ChangesOnHead<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.ends_with(b"\r\n\r\n") {
+                let mut file = fs::OpenOptions::new()
+                    .append(self.grow)
+                    .write(true)
+                    .truncate(!self.grow)
+                    .open(self.path)?;
+                if self.grow {
+                    file.write_all(b"appended")?;
+                }
+            }
+            self.out.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streams_are_chunked_or_end_the_connection() {
+        let stream = || Body::Stream(Box::new(io::Cursor::new(b"streamed".to_vec())));
+
+        let response = Response::new(Status::Ok).body(stream());
+        let (text, kept) = written(response, "GET / HTTP/1.1\r\n\r\n", true);
+        assert!(text.ends_with("Transfer-Encoding: chunked\r\n\r\n8\r\nstreamed\r\n0\r\n\r\n"));
+        assert!(kept);
+
+        let response = Response::new(Status::Ok).body(stream());
+        let (text, kept) = written(
+            response,
+            "GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+            true,
+        );
+        assert_eq!("HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nstreamed", text);
+        assert!(!kept);
+    }
+}
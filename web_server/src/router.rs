@@ -0,0 +1,371 @@
+use crate::request::Request;
+use crate::response::{Response, Status};
+
+/// Something that answers requests. Any closure taking the request and
+/// its route parameters and returning a response is one:
+///
+/// ```
+/// use web_server::{Params, Request, Response, Router, Status};
+///
+/// let mut router: Router = Router::new();
+/// router.get(
+///     "/users/:id",
+///     Box::new(|_: &Request, params: &Params| {
+///         Response::new(Status::Ok).text(format!("user {}\n", params.get("id").unwrap()))
+///     }),
+/// );
+/// ```
+pub trait Handler: Send + Sync {
+    fn handle(&self, request: &Request, params: &Params) -> Response;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&Request, &Params) -> Response + Send + Sync,
+{
+    fn handle(&self, request: &Request, params: &Params) -> Response {
+        self(request, params)
+    }
+}
+
+impl Handler for Box<dyn Handler> {
+    fn handle(&self, request: &Request, params: &Params) -> Response {
+        (**self).handle(request, params)
+    }
+}
+
+/// Values taken from the request path by the `:name` and `*name` segments
+/// of a route pattern.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params {
+    values: Vec<(String, String)>,
+}
+
+impl Params {
+    /// Returns the value matched by the segment called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    /// `:name`, which matches any one segment.
+    Param(String),
+    /// `*name`, which matches the rest of the path, slashes and all.
+    Wildcard(String),
+}
+
+struct Route<H> {
+    method: String,
+    segments: Vec<Segment>,
+    handler: H,
+}
+
+/// Why a request path found no handler.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteError {
+    /// No route matches the path.
+    NotFound,
+    /// Routes match the path, but none for the request's method. These are
+    /// the methods they do take, for the `Allow` header.
+    MethodNotAllowed(Vec<String>),
+}
+
+/// A table of handlers, each registered for a method and a path pattern.
+///
+/// A pattern is a path whose segments may be `:name`, matching any single
+/// segment, or, as the last segment, `*name`, matching everything that
+/// remains. `/users/:id` matches `/users/42`, and `/static/*path` matches
+/// `/static/css/site.css` with `path` set to `css/site.css`. Routes are
+/// tried in the order they were added and the first match wins.
+pub struct Router<H = Box<dyn Handler>> {
+    routes: Vec<Route<H>>,
+    fallback: Option<H>,
+}
+
+impl<H> Default for Router<H> {
+    fn default() -> Self {
+        Router {
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+}
+
+impl<H> Router<H> {
+    pub fn new() -> Router<H> {
+        Router::default()
+    }
+
+    /// Registers `handler` for requests with `method` whose path matches
+    /// `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// The `add` function will panic if `pattern` does not start with `/`,
+    /// or if a `*` segment is not the last one.
+    pub fn add(&mut self, method: &str, pattern: &str, handler: H) -> &mut Router<H> {
+        let segments = parse_pattern(pattern);
+        self.routes.push(Route {
+            method: method.to_string(),
+            segments,
+            handler,
+        });
+        self
+    }
+
+    pub fn get(&mut self, pattern: &str, handler: H) -> &mut Router<H> {
+        self.add("GET", pattern, handler)
+    }
+
+    pub fn post(&mut self, pattern: &str, handler: H) -> &mut Router<H> {
+        self.add("POST", pattern, handler)
+    }
+
+    pub fn put(&mut self, pattern: &str, handler: H) -> &mut Router<H> {
+        self.add("PUT", pattern, handler)
+    }
+
+    pub fn delete(&mut self, pattern: &str, handler: H) -> &mut Router<H> {
+        self.add("DELETE", pattern, handler)
+    }
+
+    /// Registers the handler for requests no route matches, in place of a
+    /// plain 404 response.
+    pub fn fallback(&mut self, handler: H) -> &mut Router<H> {
+        self.fallback = Some(handler);
+        self
+    }
+
+    /// Finds the handler for a request, along with the parameters its
+    /// pattern took from `path`.
+    pub fn route(&self, method: &str, path: &str) -> Result<(&H, Params), RouteError> {
+        let mut allowed: Vec<String> = Vec::new();
+        for route in &self.routes {
+            let Some(params) = match_segments(&route.segments, path) else {
+                continue;
+            };
+            if route.method == method {
+                return Ok((&route.handler, params));
+            }
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method.clone());
+            }
+        }
+        if allowed.is_empty() {
+            Err(RouteError::NotFound)
+        } else {
+            Err(RouteError::MethodNotAllowed(allowed))
+        }
+    }
+}
+
+impl<H: Handler> Router<H> {
+    /// Answers `request` with the handler its route leads to. A `HEAD`
+    /// request with no route of its own is answered by the `GET` handler,
+    /// and the body left out when the response is written.
+    pub fn handle(&self, request: &Request) -> Response {
+        let mut routed = self.route(&request.method, &request.path);
+        if request.method == "HEAD" && routed.is_err() {
+            routed = self.route("GET", &request.path);
+        }
+        match routed {
+            Ok((handler, params)) => handler.handle(request, &params),
+            Err(RouteError::NotFound) => match &self.fallback {
+                Some(handler) => handler.handle(request, &Params::default()),
+                None => Response::new(Status::NotFound).text("not found\n"),
+            },
+            Err(RouteError::MethodNotAllowed(mut methods)) => {
+                // HEAD is answered wherever GET is, so it is allowed there too.
+                if let Some(get) = methods.iter().position(|method| method == "GET")
+                    && !methods.iter().any(|method| method == "HEAD")
+                {
+                    methods.insert(get + 1, "HEAD".to_string());
+                }
+                Response::new(Status::MethodNotAllowed).header("Allow", &methods.join(", "))
+            }
+        }
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let rest = pattern
+        .strip_prefix('/')
+        .unwrap_or_else(|| panic!("route pattern {pattern:?} must start with '/'"));
+    let parts: Vec<&str> = rest.split('/').collect();
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            if let Some(name) = part.strip_prefix(':') {
+                Segment::Param(name.to_string())
+            } else if let Some(name) = part.strip_prefix('*') {
+                assert!(
+                    i + 1 == parts.len(),
+                    "'*{name}' must be the last segment of route pattern {pattern:?}"
+                );
+                Segment::Wildcard(name.to_string())
+            } else {
+                Segment::Literal(part.to_string())
+            }
+        })
+        .collect()
+}
+
+fn match_segments(segments: &[Segment], path: &str) -> Option<Params> {
+    let mut rest = path.strip_prefix('/')?;
+    let mut params = Params::default();
+    for (i, segment) in segments.iter().enumerate() {
+        if let Segment::Wildcard(name) = segment {
+            params.values.push((name.clone(), percent_decode(rest)));
+            return Some(params);
+        }
+        let (part, remainder) = match rest.split_once('/') {
+            Some((part, remainder)) => (part, Some(remainder)),
+            None => (rest, None),
+        };
+        match segment {
+            Segment::Literal(literal) if literal == part => {}
+            Segment::Param(name) if !part.is_empty() => {
+                params.values.push((name.clone(), percent_decode(part)));
+            }
+            _ => return None,
+        }
+        match remainder {
+            Some(remainder) => rest = remainder,
+            // The path has run out; it matches only if the pattern has too,
+            // or all that is left of the pattern is a wildcard.
+            None => {
+                return match &segments[i + 1..] {
+                    [] => Some(params),
+                    [Segment::Wildcard(name)] => {
+                        params.values.push((name.clone(), String::new()));
+                        Some(params)
+                    }
+                    _ => None,
+                };
+            }
+        }
+    }
+    None
+}
+
+/// Decodes `%XX` escapes. Anything that is not a valid escape, or that
+/// decodes to something other than UTF-8, is left as it was.
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> Router<&'static str> {
+        let mut router = Router::new();
+        router
+            .get("/", "index")
+            .get("/users/:id", "show user")
+            .put("/users/:id", "update user")
+            .get("/users/:id/posts/:post", "show post")
+            .get("/static/*path", "static");
+        router
+    }
+
+    #[test]
+    fn literal_and_parameter_segments() {
+        let router = router();
+        assert_eq!("index", *router.route("GET", "/").unwrap().0);
+
+        let (handler, params) = router.route("GET", "/users/42").unwrap();
+        assert_eq!("show user", *handler);
+        assert_eq!(Some("42"), params.get("id"));
+
+        let (handler, params) = router.route("GET", "/users/ada%20l/posts/7").unwrap();
+        assert_eq!("show post", *handler);
+        assert_eq!(Some("ada l"), params.get("id"));
+        assert_eq!(Some("7"), params.get("post"));
+
+        assert_eq!(Err(RouteError::NotFound), router.route("GET", "/users/"));
+        assert_eq!(Err(RouteError::NotFound), router.route("GET", "/users/1/x"));
+    }
+
+    #[test]
+    fn wildcards_take_the_rest_of_the_path() {
+        let router = router();
+        let (_, params) = router.route("GET", "/static/css/site.css").unwrap();
+        assert_eq!(Some("css/site.css"), params.get("path"));
+        let (_, params) = router.route("GET", "/static").unwrap();
+        assert_eq!(Some(""), params.get("path"));
+    }
+
+    #[test]
+    fn handlers_answer_requests() {
+        let mut router: Router = Router::new();
+        router.get(
+            "/users/:id",
+            Box::new(|_: &Request, params: &Params| {
+                Response::new(Status::Ok).text(params.get("id").unwrap())
+            }),
+        );
+        let request = |text: &str| Request::read_from(&mut text.as_bytes(), 0).unwrap();
+
+        let response = router.handle(&request("HEAD /users/7 HTTP/1.1\r\n\r\n"));
+        assert_eq!(Status::Ok, response.status());
+        let response = router.handle(&request("POST /users/7 HTTP/1.1\r\n\r\n"));
+        assert_eq!(Status::MethodNotAllowed, response.status());
+        assert_eq!(Some("GET, HEAD"), response.headers().get("Allow"));
+        let response = router.handle(&request("GET /posts HTTP/1.1\r\n\r\n"));
+        assert_eq!(Status::NotFound, response.status());
+
+        router.add(
+            "HEAD",
+            "/users/:id",
+            Box::new(|_: &Request, _: &Params| Response::new(Status::NoContent)),
+        );
+        let response = router.handle(&request("HEAD /users/7 HTTP/1.1\r\n\r\n"));
+        assert_eq!(Status::NoContent, response.status());
+        let response = router.handle(&request("GET /users/7 HTTP/1.1\r\n\r\n"));
+        assert_eq!(Status::Ok, response.status());
+    }
+
+    #[test]
+    fn wrong_method_lists_the_allowed_ones() {
+        assert_eq!(
+            Err(RouteError::MethodNotAllowed(vec![
+                "GET".to_string(),
+                "PUT".to_string()
+            ])),
+            router().route("DELETE", "/users/42")
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::io::{self, BufReader};
+use std::net::{Shutdown as Direction, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ThreadPool;
+use crate::config::ServerConfig;
+use crate::request::{ParseError, Request};
+use crate::response::{Response, Status};
+use crate::router::Router;
+
+/// How often the listener is checked for new connections, and so how long
+/// it can take to notice a shutdown.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Asks a running `Server` to shut down. Clones all stop the same server.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    pub fn shutdown(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+/// An HTTP server answering requests with a `Router` on a pool of worker
+/// threads.
+///
+/// It runs until shut down, either through a `Shutdown` handle or, if
+/// `stop_on_signals` was called, by SIGINT or SIGTERM. It then stops
+/// accepting connections, closes the idle ones, and gives the requests
+/// being answered until the drain timeout to finish before closing their
+/// connections too. The workers are joined before `run` returns.
+pub struct Server {
+    listener: TcpListener,
+    router: Arc<Router>,
+    workers: usize,
+    idle_timeout: Duration,
+    max_requests: usize,
+    max_body_size: usize,
+    drain_timeout: Duration,
+    signals: bool,
+    shutdown: Shutdown,
+}
+
+impl Server {
+    /// Listens on `addr`, with the default settings of `ServerConfig`.
+    pub fn bind(addr: impl ToSocketAddrs, router: Router) -> io::Result<Server> {
+        let defaults = ServerConfig::default();
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            router: Arc::new(router),
+            workers: defaults.workers,
+            idle_timeout: defaults.idle_timeout,
+            max_requests: defaults.max_requests,
+            max_body_size: defaults.max_body_size,
+            drain_timeout: defaults.drain_timeout,
+            signals: false,
+            shutdown: Shutdown::default(),
+        })
+    }
+
+    /// Listens on the address and port in `config`, with its settings.
+    pub fn from_config(config: &ServerConfig, router: Router) -> io::Result<Server> {
+        Ok(Server::bind((config.address, config.port), router)?
+            .workers(config.workers)
+            .idle_timeout(config.idle_timeout)
+            .max_requests(config.max_requests)
+            .max_body_size(config.max_body_size)
+            .drain_timeout(config.drain_timeout))
+    }
+
+    /// Sets the number of worker threads, and so of connections served at
+    /// once. `run` panics if it is zero.
+    pub fn workers(mut self, workers: usize) -> Server {
+        self.workers = workers;
+        self
+    }
+
+    /// Sets how long a connection may sit idle between requests before it
+    /// is closed.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Server {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Sets the most requests answered on one connection before it is
+    /// closed.
+    pub fn max_requests(mut self, max: usize) -> Server {
+        self.max_requests = max;
+        self
+    }
+
+    /// Sets the largest request body accepted, in bytes.
+    pub fn max_body_size(mut self, size: usize) -> Server {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Sets how long requests being answered at shutdown get to finish.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Server {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Shuts the server down on SIGINT or SIGTERM, as a supervisor sends.
+    /// Does nothing on platforms without signals.
+    pub fn stop_on_signals(mut self) -> Server {
+        self.signals = true;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Serves connections until shut down.
+    pub fn run(self) -> io::Result<()> {
+        if self.signals {
+            signals::install();
+        }
+        self.listener.set_nonblocking(true)?;
+        let pool = ThreadPool::new(self.workers);
+        let connections = Arc::new(Connections::default());
+
+        while !self.shutdown.is_requested() {
+            if self.signals && signals::received() {
+                self.shutdown.shutdown();
+                break;
+            }
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_INTERVAL);
+                    continue;
+                }
+                // Failures like running out of file descriptors pass; the
+                // listener itself is still fine.
+                Err(e) => {
+                    eprintln!("Failed to accept a connection: {e}");
+                    thread::sleep(ACCEPT_INTERVAL);
+                    continue;
+                }
+            };
+            // Some platforms hand out connections as nonblocking as the
+            // listener.
+            if stream.set_nonblocking(false).is_err() {
+                continue;
+            }
+            let Some(id) = connections.add(&stream) else {
+                continue;
+            };
+            let connection = Connection {
+                id,
+                router: Arc::clone(&self.router),
+                connections: Arc::clone(&connections),
+                shutdown: self.shutdown.clone(),
+                idle_timeout: self.idle_timeout,
+                max_requests: self.max_requests,
+                max_body_size: self.max_body_size,
+            };
+            pool.execute(move || connection.serve(stream));
+        }
+
+        drop(self.listener);
+        connections.drain(Instant::now() + self.drain_timeout);
+        // Joins the workers.
+        drop(pool);
+        Ok(())
+    }
+}
+
+/// The open connections, so that they can be closed at shutdown.
+#[derive(Default)]
+struct Connections {
+    open: Mutex<OpenConnections>,
+    closed: Condvar,
+}
+
+#[derive(Default)]
+struct OpenConnections {
+    next_id: u64,
+    /// Each connection, and whether it is waiting for a request.
+    streams: HashMap<u64, (TcpStream, bool)>,
+    draining: bool,
+}
+
+impl Connections {
+    fn add(&self, stream: &TcpStream) -> Option<u64> {
+        let stream = stream.try_clone().ok()?;
+        let mut open = self.open.lock().unwrap();
+        let id = open.next_id;
+        open.next_id += 1;
+        open.streams.insert(id, (stream, false));
+        Some(id)
+    }
+
+    /// Marks a connection as waiting for its next request, or not. Returns
+    /// `false` if it should close instead of waiting, because the server
+    /// is shutting down.
+    fn set_idle(&self, id: u64, idle: bool) -> bool {
+        let mut open = self.open.lock().unwrap();
+        if idle && open.draining {
+            return false;
+        }
+        if let Some((_, state)) = open.streams.get_mut(&id) {
+            *state = idle;
+        }
+        true
+    }
+
+    fn remove(&self, id: u64) {
+        self.open.lock().unwrap().streams.remove(&id);
+        self.closed.notify_all();
+    }
+
+    /// Closes the idle connections and waits for the others to finish,
+    /// closing whichever are still open at `deadline`.
+    fn drain(&self, deadline: Instant) {
+        let mut open = self.open.lock().unwrap();
+        open.draining = true;
+        for (stream, idle) in open.streams.values() {
+            if *idle {
+                let _ = stream.shutdown(Direction::Read);
+            }
+        }
+        while !open.streams.is_empty() {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            open = self.closed.wait_timeout(open, left).unwrap().0;
+        }
+        for (stream, _) in open.streams.values() {
+            let _ = stream.shutdown(Direction::Both);
+        }
+    }
+}
+
+/// One client connection, and what is needed to answer its requests.
+struct Connection {
+    id: u64,
+    router: Arc<Router>,
+    connections: Arc<Connections>,
+    shutdown: Shutdown,
+    idle_timeout: Duration,
+    max_requests: usize,
+    max_body_size: usize,
+}
+
+impl Connection {
+    /// Answers requests until the client closes the connection, asks for
+    /// it to be closed or leaves it idle too long, or until the server shuts
+    /// down. Pipelined requests are answered in order, since they are read
+    /// from the same buffer one at a time.
+    fn serve(self, stream: TcpStream) {
+        if stream.set_read_timeout(Some(self.idle_timeout)).is_ok() {
+            self.serve_requests(&stream);
+        }
+        self.connections.remove(self.id);
+    }
+
+    fn serve_requests(&self, stream: &TcpStream) {
+        let mut buf_reader = BufReader::new(stream);
+        let mut stream = stream;
+
+        for served in 1..=self.max_requests {
+            // At shutdown, a connection that has had its request answered
+            // is done; one still waiting for its first gets until the
+            // drain deadline.
+            if !self.connections.set_idle(self.id, true) && served > 1 {
+                return;
+            }
+            let request = Request::read_from(&mut buf_reader, self.max_body_size);
+            self.connections.set_idle(self.id, false);
+            let request = match request {
+                Ok(request) => request,
+                // Closed by the client, idle past the timeout, or closed at
+                // shutdown.
+                Err(ParseError::Closed | ParseError::Io(_)) => return,
+                Err(e) => {
+                    let status = match e {
+                        ParseError::BodyTooLarge => Status::ContentTooLarge,
+                        _ => Status::BadRequest,
+                    };
+                    let response = Response::new(status).text(format!("{e}\n"));
+                    let _ = response.write_to(&mut stream, None, false);
+                    return;
+                }
+            };
+
+            let response = self.router.handle(&request);
+            let keep_alive =
+                request.keep_alive() && served < self.max_requests && !self.shutdown.is_requested();
+            match response.write_to(&mut stream, Some(&request), keep_alive) {
+                Ok(true) => {}
+                Ok(false) | Err(_) => return,
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::ffi::c_int;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn handle(_signum: c_int) {
+        // Storing to an atomic is all that is safe to do in a handler.
+        RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        // SAFETY: the handler only stores to an atomic, which is
+        // async-signal-safe.
+        unsafe {
+            signal(SIGINT, handle);
+            signal(SIGTERM, handle);
+        }
+    }
+
+    pub fn received() -> bool {
+        RECEIVED.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub fn install() {}
+
+    pub fn received() -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Params;
+    use std::io::{Read, Write};
+
+    fn server() -> Server {
+        let mut router: Router = Router::new();
+        router.get(
+            "/slow",
+            Box::new(|_: &Request, _: &Params| {
+                thread::sleep(Duration::from_millis(300));
+                Response::new(Status::Ok).text("done")
+            }),
+        );
+        Server::bind("127.0.0.1:0", router).unwrap().workers(2)
+    }
+
+    #[test]
+    fn shutdown_finishes_requests_in_flight() {
+        let server = server();
+        let addr = server.local_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let mut idle = TcpStream::connect(addr).unwrap();
+        let mut busy = TcpStream::connect(addr).unwrap();
+        busy.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        shutdown.shutdown();
+
+        let mut response = String::new();
+        busy.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.contains("Connection: close"), "{response}");
+        assert!(response.ends_with("done"), "{response}");
+        assert_eq!(0, idle.read(&mut [0; 16]).unwrap());
+
+        running.join().unwrap().unwrap();
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn drain_timeout_cuts_off_slow_requests() {
+        let server = server().drain_timeout(Duration::from_millis(50));
+        let addr = server.local_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let mut busy = TcpStream::connect(addr).unwrap();
+        busy.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let started = Instant::now();
+        shutdown.shutdown();
+
+        // The handler still runs to the end, but its response goes nowhere.
+        let mut response = Vec::new();
+        let _ = busy.read_to_end(&mut response);
+        assert!(response.is_empty());
+        running.join().unwrap().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
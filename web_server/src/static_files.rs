@@ -0,0 +1,405 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::headers::Headers;
+use crate::request::Request;
+use crate::response::{Response, Status};
+
+/// What serving a static file came to.
+#[derive(Debug)]
+pub enum Served {
+    /// The file, opened, with the headers that describe it.
+    File {
+        headers: Headers,
+        file: File,
+    },
+    /// The client's copy is current, going by `If-None-Match` or
+    /// `If-Modified-Since`.
+    NotModified {
+        headers: Headers,
+    },
+    /// A directory was asked for without its trailing slash; this is where
+    /// to send the client instead, so that relative links in its index
+    /// resolve.
+    Redirect(String),
+    NotFound,
+    /// The path tried to leave the document root.
+    Forbidden,
+}
+
+/// Serves the files under a document root.
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles { root: root.into() }
+    }
+
+    /// Answers `request` with the file at `path`, as `serve` finds it.
+    pub fn respond(&self, path: &str, request: &Request) -> Response {
+        match self.serve(path, request) {
+            Ok(Served::File { headers, file }) => {
+                Response::new(Status::Ok).headers_from(&headers).body(file)
+            }
+            Ok(Served::NotModified { headers }) => {
+                Response::new(Status::NotModified).headers_from(&headers)
+            }
+            Ok(Served::Redirect(location)) => {
+                Response::redirect(Status::MovedPermanently, &location)
+            }
+            Ok(Served::NotFound) => self.not_found(),
+            Ok(Served::Forbidden) => Response::new(Status::Forbidden).text("forbidden\n"),
+            Err(e) => Response::new(Status::InternalServerError).text(format!("{e}\n")),
+        }
+    }
+
+    /// A 404 response: the document root's `404.html` if it has one, or a
+    /// plain message if not.
+    pub fn not_found(&self) -> Response {
+        match File::open(self.root.join("404.html")) {
+            Ok(page) => Response::new(Status::NotFound)
+                .header("Content-Type", "text/html; charset=utf-8")
+                .body(page),
+            Err(_) => Response::new(Status::NotFound).text("not found\n"),
+        }
+    }
+
+    /// Serves the file at `path`, relative to the document root, for
+    /// `request`. A directory is served by its `index.html`.
+    ///
+    /// `path` must already be percent-decoded. Any `..` in it is refused,
+    /// as is a symbolic link that leads out of the document root.
+    pub fn serve(&self, path: &str, request: &Request) -> io::Result<Served> {
+        let mut file = self.root.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => file.push(part),
+                Component::CurDir => {}
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                    return Ok(Served::Forbidden);
+                }
+            }
+        }
+
+        let file = match file.canonicalize() {
+            Ok(file) => file,
+            Err(e) if is_missing(&e) => return Ok(Served::NotFound),
+            Err(e) => return Err(e),
+        };
+        if !file.starts_with(self.root.canonicalize()?) {
+            return Ok(Served::Forbidden);
+        }
+        let file = if file.is_dir() {
+            if !request.path.ends_with('/') {
+                let location = match &request.query {
+                    Some(query) => format!("{}/?{query}", request.path),
+                    None => format!("{}/", request.path),
+                };
+                return Ok(Served::Redirect(location));
+            }
+            file.join("index.html")
+        } else {
+            file
+        };
+
+        let metadata = match fs::metadata(&file) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => return Ok(Served::NotFound),
+            Err(e) if is_missing(&e) => return Ok(Served::NotFound),
+            Err(e) => return Err(e),
+        };
+        let modified = metadata.modified()?;
+        let etag = format!(
+            "\"{:x}-{:x}\"",
+            metadata.len(),
+            modified
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+        );
+
+        let mut headers = Headers::new();
+        headers.append("ETag", &etag);
+        headers.append("Last-Modified", &http_date(modified));
+        if is_fresh(&request.headers, &etag, modified) {
+            return Ok(Served::NotModified { headers });
+        }
+
+        headers.append("Content-Type", mime_type(&file));
+        let file = File::open(&file)?;
+        Ok(Served::File { headers, file })
+    }
+}
+
+fn is_missing(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+    )
+}
+
+/// Whether the client's cached copy, as described by its conditional
+/// headers, is still current. `If-None-Match` wins over
+/// `If-Modified-Since` when both are sent.
+fn is_fresh(headers: &Headers, etag: &str, modified: SystemTime) -> bool {
+    if let Some(tags) = headers.get("If-None-Match") {
+        return tags
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag);
+    }
+    match headers.get("If-Modified-Since").and_then(parse_http_date) {
+        // HTTP dates only go down to the second.
+        Some(since) => !modified
+            .duration_since(since)
+            .is_ok_and(|newer| newer >= Duration::from_secs(1)),
+        None => false,
+    }
+}
+
+/// Guesses a file's media type from its extension.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "mp4" => "video/mp4",
+        "mp3" => "audio/mpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats `time` the way HTTP wants dates, as in
+/// `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let days = secs / 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+    let seconds = secs % 86_400;
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        MONTHS[month as usize - 1],
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+    )
+}
+
+/// Reads a date in the format `http_date` writes. The obsolete formats
+/// HTTP still allows clients to send are not understood.
+pub(crate) fn parse_http_date(text: &str) -> Option<SystemTime> {
+    let (_, rest) = text.split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let (Some(day), Some(month), Some(year), Some(time), Some("GMT"), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return None;
+    };
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|&name| name == month)? as u32 + 1;
+    let year: i64 = year.parse().ok()?;
+    let mut clock = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (Some(Some(hours)), Some(Some(minutes)), Some(Some(seconds)), None) =
+        (clock.next(), clock.next(), clock.next(), clock.next())
+    else {
+        return None;
+    };
+    if !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days * 86_400 + hours * 3600 + minutes * 60 + seconds;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date, after
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The inverse of `civil_from_days`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::process;
+
+    fn get(path: &str, headers: &[(&str, &str)]) -> Request {
+        let mut request = Request {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: None,
+            version: "HTTP/1.1".to_string(),
+            headers: Headers::new(),
+            body: Vec::new(),
+        };
+        for (name, value) in headers {
+            request.headers.append(name, value);
+        }
+        request
+    }
+
+    #[test]
+    fn http_dates() {
+        let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", http_date(time));
+        assert_eq!(Some(time), parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+        assert_eq!("Thu, 29 Feb 2024 00:00:00 GMT", {
+            http_date(UNIX_EPOCH + Duration::from_secs(1_709_164_800))
+        });
+    }
+
+    #[test]
+    fn serves_files_and_refuses_to_leave_the_root() {
+        let dir = std::env::temp_dir().join(format!("web-server-static-{}", process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("logo.png"), [0x89, b'P', b'N', b'G', 0xff]).unwrap();
+        fs::write(dir.join("docs/index.html"), "<h1>Docs</h1>").unwrap();
+        let files = StaticFiles::new(&dir);
+
+        let contents = |served| match served {
+            Served::File { mut file, .. } => {
+                let mut contents = Vec::new();
+                file.read_to_end(&mut contents).unwrap();
+                contents
+            }
+            served => panic!("expected a file, got {served:?}"),
+        };
+
+        let served = files.serve("logo.png", &get("/logo.png", &[])).unwrap();
+        assert!(
+            matches!(&served, Served::File { headers, .. } if headers.get("Content-Type") == Some("image/png"))
+        );
+        assert_eq!(vec![0x89, b'P', b'N', b'G', 0xff], contents(served));
+
+        let served = files.serve("docs/", &get("/docs/", &[])).unwrap();
+        assert_eq!(b"<h1>Docs</h1>", &contents(served)[..]);
+        assert!(matches!(
+            files.serve("docs", &get("/docs", &[])).unwrap(),
+            Served::Redirect(location) if location == "/docs/"
+        ));
+        assert!(matches!(
+            files
+                .serve("missing.txt", &get("/missing.txt", &[]))
+                .unwrap(),
+            Served::NotFound
+        ));
+        assert!(matches!(
+            files
+                .serve("docs/../../etc/passwd", &get("/", &[]))
+                .unwrap(),
+            Served::Forbidden
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_files_without_a_404_page() {
+        let dir = std::env::temp_dir().join(format!("web-server-404-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = StaticFiles::new(&dir);
+
+        let response = files.respond("missing.txt", &get("/missing.txt", &[]));
+        assert_eq!(Status::NotFound, response.status());
+
+        fs::write(dir.join("404.html"), "<h1>Gone</h1>").unwrap();
+        let response = files.respond("missing.txt", &get("/missing.txt", &[]));
+        assert_eq!(Status::NotFound, response.status());
+        assert_eq!(
+            Some("text/html; charset=utf-8"),
+            response.headers().get("Content-Type")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let dir = std::env::temp_dir().join(format!("web-server-cache-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("site.css"), "body {}").unwrap();
+        let files = StaticFiles::new(&dir);
+
+        let Served::File { headers, .. } = files.serve("site.css", &get("/", &[])).unwrap() else {
+            panic!("site.css should be served");
+        };
+        let etag = headers.get("ETag").unwrap();
+        let modified = headers.get("Last-Modified").unwrap();
+
+        for conditions in [
+            [("If-None-Match", etag)],
+            [("If-None-Match", format!("\"other\", W/{etag}").as_str())],
+            [("If-Modified-Since", modified)],
+        ] {
+            let served = files.serve("site.css", &get("/", &conditions)).unwrap();
+            assert!(
+                matches!(served, Served::NotModified { .. }),
+                "{conditions:?}"
+            );
+        }
+        let stale = [("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT")];
+        let served = files.serve("site.css", &get("/", &stale)).unwrap();
+        assert!(matches!(served, Served::File { .. }));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}